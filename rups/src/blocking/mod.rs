@@ -1,29 +1,155 @@
-use std::io::{BufRead, BufReader, Write};
+use std::io::{BufRead, BufReader, Read, Write};
 use std::net::{SocketAddr, TcpStream};
+use std::time::Instant;
 
 use crate::blocking::stream::ConnectionStream;
-use crate::cmd::{Command, Response};
-use crate::{ClientError, Config, Host, NutError};
+use crate::cmd::{Command, Response, PIPELINE_BATCH};
+use crate::proto::util::{is_mutating, join_sentence, split_sentence};
+use crate::proto::{ClientSentences, ServerSentences};
+use crate::{ClientError, Config, Host, NutError, ServerVersion, Variable};
 
+pub use shared::SharedConnection;
+
+mod shared;
 mod stream;
 
 /// A blocking NUT client connection.
+///
+/// A connection is a single stream, and requires `&mut self` for every request. To share it
+/// between threads, see [`SharedConnection`].
 pub enum Connection {
-    /// A TCP connection.
+    /// A TCP connection (or a custom stream, see [`Connection::from_stream`]).
     Tcp(TcpConnection),
 }
 
 impl Connection {
     /// Initializes a connection to a NUT server (upsd).
+    ///
+    /// If connecting to the host (or logging in) fails, each fallback host is tried in turn
+    /// (see [`ConfigBuilder::with_fallback_hosts`](crate::ConfigBuilder::with_fallback_hosts)),
+    /// and the last error is returned if none succeeds.
     pub fn new(config: &Config) -> crate::Result<Self> {
-        let mut conn = match &config.host {
-            Host::Tcp(host) => Self::Tcp(TcpConnection::new(config.clone(), &host.addr)?),
+        let mut result = Self::connect(config);
+        for host in &config.fallback_hosts {
+            match &result {
+                Ok(_) => break,
+                Err(e) if config.debug => eprintln!("DEBUG <- (!) Connection failed: {}", e),
+                Err(_) => {}
+            }
+            let config = Config {
+                host: host.clone(),
+                ..config.clone()
+            };
+            result = Self::connect(&config);
+        }
+        result
+    }
+
+    /// Connects to the configured host, without fallback.
+    fn connect(config: &Config) -> crate::Result<Self> {
+        let conn = match &config.host {
+            Host::Tcp(host) => {
+                let addr = host.resolve(config.address_family)?;
+                Self::Tcp(TcpConnection::new(config.clone(), &addr)?)
+            }
         };
+        conn.initialize(config)
+    }
+
+    /// Initializes a connection to a NUT server (upsd) over an already-established stream,
+    /// such as an SSH tunnel or a custom transport.
+    ///
+    /// The host of the configuration is ignored, but SSL and authentication are
+    /// performed as configured.
+    pub fn from_stream<S>(config: &Config, stream: S) -> crate::Result<Self>
+    where
+        S: Read + Write + Send + 'static,
+    {
+        let conn = Self::Tcp(TcpConnection::with_stream(
+            config.clone(),
+            ConnectionStream::Custom(Box::new(stream)),
+        )?);
+        conn.initialize(config)
+    }
+
+    /// Logs in and probes the server, as applicable.
+    fn initialize(mut self, config: &Config) -> crate::Result<Self> {
+        if config.auto_login {
+            self.login(config)?;
+        }
+        if config.probe_version {
+            let network_version = self.probe_network_version(config)?;
+            let server_version = self.probe_server_version(config)?;
+            match &mut self {
+                Self::Tcp(conn) => {
+                    conn.network_version = network_version;
+                    conn.server_version = server_version;
+                }
+            }
+        }
+        Ok(self)
+    }
+
+    /// Whether the connection is encrypted with SSL.
+    ///
+    /// Connecting fails if SSL was requested but could not be enabled, so this is only
+    /// `false` when SSL was not requested.
+    pub fn is_tls(&self) -> bool {
+        match self {
+            Self::Tcp(conn) => conn.stream.get_ref().is_ssl(),
+        }
+    }
+
+    /// The negotiated TLS protocol version and cipher suite, or `None` if SSL is not enabled.
+    #[cfg(feature = "ssl")]
+    pub fn tls_info(&self) -> Option<crate::TlsInfo> {
+        match self {
+            Self::Tcp(conn) => conn.stream.get_ref().tls_info(),
+        }
+    }
+
+    /// The underlying TCP socket, to tune options that the configuration doesn't cover
+    /// (e.g. `set_ttl`).
+    ///
+    /// This is `None` for SSL connections and custom streams (see `from_stream`).
+    ///
+    /// The socket must only be used to set options, never for I/O (through its `Read` and
+    /// `Write` implementations): the client buffers what it reads, so data sent or received
+    /// behind its back would desynchronize the protocol.
+    pub fn tcp_stream(&self) -> Option<&TcpStream> {
+        match self {
+            Self::Tcp(conn) => conn.stream.get_ref().tcp_stream(),
+        }
+    }
 
-        conn.get_network_version()?;
-        conn.login(config)?;
+    /// The network protocol version, as reported by the server when connecting.
+    ///
+    /// This is `None` if the server doesn't support `NETVER`, or if the probe is disabled
+    /// (see [`ConfigBuilder::with_version_probe`](crate::ConfigBuilder::with_version_probe)).
+    pub fn network_version(&self) -> Option<&str> {
+        match self {
+            Self::Tcp(conn) => conn.network_version.as_deref(),
+        }
+    }
 
-        Ok(conn)
+    /// The server NUT version, as reported by the server when connecting.
+    ///
+    /// This is `None` if the server doesn't support `VER`, or if the probe is disabled
+    /// (see [`ConfigBuilder::with_version_probe`](crate::ConfigBuilder::with_version_probe)).
+    pub fn server_version(&self) -> Option<&ServerVersion> {
+        match self {
+            Self::Tcp(conn) => conn.server_version.as_ref(),
+        }
+    }
+
+    /// Sets (or clears) the deadline for subsequent requests.
+    ///
+    /// Once it has passed, requests fail with [`NutError::Timeout`]. The connection should not
+    /// be reused after a timeout, since a response may still be pending.
+    pub fn set_deadline(&mut self, deadline: Option<Instant>) -> crate::Result<()> {
+        match self {
+            Self::Tcp(conn) => conn.set_deadline(deadline),
+        }
     }
 
     /// Gracefully closes the connection.
@@ -32,9 +158,83 @@ impl Connection {
         Ok(())
     }
 
+    /// Gracefully closes the connection, giving up on the logout after the given duration.
+    ///
+    /// Unlike [`close`](Self::close), this doesn't hang if the server is gone: the socket is
+    /// then simply dropped. Since the connection is torn down either way, this never fails.
+    pub fn close_with_timeout(mut self, timeout: std::time::Duration) -> crate::Result<()> {
+        if self.set_deadline(Some(Instant::now() + timeout)).is_ok() {
+            let _ = self.logout();
+        }
+        Ok(())
+    }
+
+    /// Lazily iterates over the variables of every UPS device, as `(ups_name, variable)` pairs.
+    ///
+    /// The devices are listed on the first call to `next`, and the variables of each device
+    /// are queried once the previous device is exhausted. Iteration stops after the first error.
+    pub fn iter_all_vars(&mut self) -> AllVars<'_> {
+        AllVars {
+            conn: self,
+            devices: None,
+            current: None,
+            done: false,
+        }
+    }
+
+    /// Queries the network version, tolerating old servers that don't support `NETVER`.
+    fn probe_network_version(&mut self, config: &Config) -> crate::Result<Option<String>> {
+        match self.get_network_version() {
+            Ok(version) => Ok(Some(version)),
+            Err(ClientError::Nut(NutError::UnknownCommand)) => {
+                if config.debug {
+                    eprintln!("DEBUG <- (!) NETVER is not supported by the server");
+                }
+                Ok(None)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Queries the server version, tolerating servers that don't support `VER`.
+    fn probe_server_version(&mut self, config: &Config) -> crate::Result<Option<ServerVersion>> {
+        match self.get_server_version_parsed() {
+            Ok(version) => Ok(Some(version)),
+            Err(ClientError::Nut(NutError::UnknownCommand)) => {
+                if config.debug {
+                    eprintln!("DEBUG <- (!) VER is not supported by the server");
+                }
+                Ok(None)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Sends the configured username and password, and checks that the server accepts them.
+    ///
+    /// This is meant for connections with auto-login disabled
+    /// (see [`ConfigBuilder::with_auto_login`](crate::ConfigBuilder::with_auto_login));
+    /// otherwise, the credentials were already sent and cannot be sent again. Rejections are
+    /// returned as the specific error (e.g. [`NutError::InvalidPassword`]). Note that upsd may
+    /// only fully check the credentials when they are first used for a privileged command.
+    pub fn verify_auth(&mut self) -> crate::Result<()> {
+        let config = match self {
+            Self::Tcp(conn) => conn.config.clone(),
+        };
+        if config.auth.is_none() {
+            return Err(NutError::UsernameRequired.into());
+        }
+        self.login(&config)
+    }
+
     /// Sends username and password, as applicable.
     fn login(&mut self, config: &Config) -> crate::Result<()> {
         if let Some(auth) = config.auth.clone() {
+            // upsd only accepts a password after a username, so don't bother sending it
+            if auth.username.is_empty() {
+                return Err(NutError::UsernameRequired.into());
+            }
+
             // Pass username and check for 'OK'
             self.set_username(&auth.username)?;
 
@@ -47,21 +247,108 @@ impl Connection {
     }
 }
 
+/// An iterator over the variables of every UPS device.
+///
+/// Created by [`Connection::iter_all_vars`].
+pub struct AllVars<'a> {
+    conn: &'a mut Connection,
+    devices: Option<std::vec::IntoIter<(String, String)>>,
+    current: Option<(String, std::vec::IntoIter<Variable>)>,
+    done: bool,
+}
+
+impl<'a> Iterator for AllVars<'a> {
+    type Item = crate::Result<(String, Variable)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.done {
+            if let Some((ups_name, vars)) = &mut self.current {
+                if let Some(var) = vars.next() {
+                    return Some(Ok((ups_name.clone(), var)));
+                }
+            }
+
+            let devices = match &mut self.devices {
+                Some(devices) => devices,
+                None => match self.conn.list_ups() {
+                    Ok(devices) => self.devices.insert(devices.into_iter()),
+                    Err(e) => {
+                        self.done = true;
+                        return Some(Err(e));
+                    }
+                },
+            };
+
+            if let Some((ups_name, _)) = devices.next() {
+                match self.conn.list_vars(&ups_name) {
+                    Ok(vars) => self.current = Some((ups_name, vars.into_iter())),
+                    Err(e) => {
+                        self.done = true;
+                        return Some(Err(e));
+                    }
+                }
+            } else {
+                self.done = true;
+            }
+        }
+        None
+    }
+}
+
 /// A blocking TCP NUT client connection.
 pub struct TcpConnection {
     config: Config,
-    stream: ConnectionStream,
+    stream: BufReader<ConnectionStream>,
+    network_version: Option<String>,
+    pub(crate) server_version: Option<ServerVersion>,
+    pub(crate) capabilities: Option<crate::Capabilities>,
+    deadline: Option<Instant>,
 }
 
 impl TcpConnection {
     fn new(config: Config, socket_addr: &SocketAddr) -> crate::Result<Self> {
-        // Create the TCP connection
-        let tcp_stream = TcpStream::connect_timeout(socket_addr, config.timeout)?;
-        let mut connection = Self {
+        // Create the TCP connection, within the deadline (if any)
+        let timeout = match config.deadline {
+            Some(deadline) => config.timeout.min(Self::remaining(deadline)?),
+            None => config.timeout,
+        };
+        let connect = || match config.bind_address {
+            Some(bind_address) => {
+                let socket = socket2::Socket::new(
+                    socket2::Domain::for_address(*socket_addr),
+                    socket2::Type::STREAM,
+                    Some(socket2::Protocol::TCP),
+                )?;
+                socket.bind(&bind_address.into())?;
+                socket.connect_timeout(&(*socket_addr).into(), timeout)?;
+                Ok(TcpStream::from(socket))
+            }
+            None => TcpStream::connect_timeout(socket_addr, timeout),
+        };
+        let tcp_stream = connect().map_err(|e| match config.deadline {
+            Some(deadline) if Self::remaining(deadline).is_err() => NutError::Timeout.into(),
+            _ => ClientError::Io(e),
+        })?;
+        tcp_stream.set_nodelay(config.tcp_nodelay)?;
+        Self::with_stream(config, ConnectionStream::Plain(tcp_stream))
+    }
+
+    fn with_stream(config: Config, stream: ConnectionStream) -> crate::Result<Self> {
+        stream.set_timeouts(config.read_timeout, config.write_timeout)?;
+        let connection = Self {
+            deadline: config.deadline,
             config,
-            stream: ConnectionStream::Plain(tcp_stream),
+            stream: BufReader::new(stream),
+            network_version: None,
+            server_version: None,
+            capabilities: None,
         };
-        connection = connection.enable_ssl()?;
+        let connection = connection.enable_ssl()?;
+        // Never fall back to plain text when SSL was requested (e.g. if SSL support is not
+        // compiled in), since the credentials would be sent in the clear.
+        if connection.config.ssl && !connection.stream.get_ref().is_ssl() {
+            return Err(NutError::SslNotSupported.into());
+        }
         Ok(connection)
     }
 
@@ -80,36 +367,33 @@ impl TcpConnection {
                 .expect_ok()?;
 
             let mut ssl_config = rustls::ClientConfig::new();
-            let sess = if self.config.ssl_insecure {
+            let sess = if let Some(custom) = &self.config.ssl_client_config {
+                // Use the given configuration as-is; its verifier checks the hostname
+                let dns_name = crate::ssl::server_name(&self.config)?;
+                rustls::ClientSession::new(&custom.0, dns_name.as_ref())
+            } else if self.config.ssl_insecure || self.config.ssl_pinned_cert.is_some() {
                 ssl_config
                     .dangerous()
                     .set_certificate_verifier(std::sync::Arc::new(
                         crate::ssl::InsecureCertificateValidator::new(&self.config),
                     ));
 
-                let dns_name = webpki::DNSNameRef::try_from_ascii_str("www.google.com").unwrap();
-
-                rustls::ClientSession::new(&std::sync::Arc::new(ssl_config), dns_name)
+                let dns_name = crate::ssl::insecure_server_name(&self.config);
+                rustls::ClientSession::new(&std::sync::Arc::new(ssl_config), dns_name.as_ref())
             } else {
-                // Try to get hostname as given (e.g. localhost can be used for strict SSL, but not 127.0.0.1)
-                let hostname = self
-                    .config
-                    .host
-                    .hostname()
-                    .ok_or(ClientError::Nut(NutError::SslInvalidHostname))?;
-
-                let dns_name = webpki::DNSNameRef::try_from_ascii_str(&hostname)
-                    .map_err(|_| ClientError::Nut(NutError::SslInvalidHostname))?;
+                // Use the hostname as given (e.g. localhost can be used for strict SSL, but not 127.0.0.1)
+                let dns_name = crate::ssl::server_name(&self.config)?;
 
                 ssl_config
                     .root_store
                     .add_server_trust_anchors(&webpki_roots::TLS_SERVER_ROOTS);
 
-                rustls::ClientSession::new(&std::sync::Arc::new(ssl_config), dns_name)
+                rustls::ClientSession::new(&std::sync::Arc::new(ssl_config), dns_name.as_ref())
             };
 
-            // Wrap and override the TCP stream
-            self.stream = self.stream.upgrade_ssl(sess)?;
+            // Wrap and override the TCP stream. Nothing is buffered at this point,
+            // since the server waits for the TLS handshake.
+            self.stream = BufReader::new(self.stream.into_inner().upgrade_ssl(sess)?);
         }
         Ok(self)
     }
@@ -119,65 +403,1795 @@ impl TcpConnection {
         Ok(self)
     }
 
-    pub(crate) fn write_cmd(&mut self, line: Command) -> crate::Result<()> {
-        let line = format!("{}\n", line);
-        if self.config.debug {
-            eprint!("DEBUG -> {}", line);
+    fn set_deadline(&mut self, deadline: Option<Instant>) -> crate::Result<()> {
+        self.deadline = deadline;
+        if deadline.is_none() {
+            self.stream
+                .get_ref()
+                .set_timeouts(self.config.read_timeout, self.config.write_timeout)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the time left until the deadline, or a timeout error if it has passed.
+    fn remaining(deadline: Instant) -> crate::Result<std::time::Duration> {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            Err(NutError::Timeout.into())
+        } else {
+            Ok(remaining)
+        }
+    }
+
+    /// Limits the next I/O operation to the time left until the deadline, if any
+    /// (or to the configured read and write timeouts, if shorter).
+    fn apply_deadline(&mut self) -> crate::Result<()> {
+        if let Some(deadline) = self.deadline {
+            let remaining = Self::remaining(deadline)?;
+            let limit = |timeout: Option<std::time::Duration>| {
+                Some(timeout.map_or(remaining, |timeout| timeout.min(remaining)))
+            };
+            self.stream.get_ref().set_timeouts(
+                limit(self.config.read_timeout),
+                limit(self.config.write_timeout),
+            )?;
         }
-        self.stream.write_all(line.as_bytes())?;
-        self.stream.flush()?;
         Ok(())
     }
 
-    fn parse_line(
-        reader: &mut BufReader<&mut ConnectionStream>,
-        debug: bool,
-    ) -> crate::Result<Vec<String>> {
+    /// Maps I/O timeouts to [`NutError::Timeout`] when a deadline or timeouts are set.
+    fn map_timeout(&self, err: ClientError) -> ClientError {
+        let has_timeouts = self.deadline.is_some()
+            || self.config.read_timeout.is_some()
+            || self.config.write_timeout.is_some();
+        match err {
+            ClientError::Io(e)
+                if has_timeouts
+                    && matches!(
+                        e.kind(),
+                        std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+                    ) =>
+            {
+                NutError::Timeout.into()
+            }
+            err => err,
+        }
+    }
+
+    pub(crate) fn config(&self) -> &Config {
+        &self.config
+    }
+
+    pub(crate) fn write_cmd(&mut self, line: Command) -> crate::Result<()> {
+        self.write_line(&line.to_string())
+    }
+
+    pub(crate) fn write_line(&mut self, line: &str) -> crate::Result<()> {
+        self.write_lines(&[line])
+    }
+
+    /// Writes several command lines at once, without waiting for their responses (pipelining).
+    pub(crate) fn write_lines<S: AsRef<str>>(&mut self, lines: &[S]) -> crate::Result<()> {
+        if lines
+            .iter()
+            .any(|line| line.as_ref().contains(&['\n', '\r'][..]))
+        {
+            return Err(NutError::generic("Command line cannot contain a line break").into());
+        }
+        if self.config.read_only && lines.iter().any(|line| is_mutating(line.as_ref())) {
+            return Err(NutError::ReadOnly.into());
+        }
+        let mut buf = String::new();
+        for line in lines {
+            let line = format!("{}\n", line.as_ref());
+            if self.config.debug {
+                eprint!("DEBUG -> {}", line);
+            }
+            buf.push_str(&line);
+        }
+        self.apply_deadline()?;
+        let stream = self.stream.get_mut();
+        stream
+            .write_all(buf.as_bytes())
+            .and_then(|_| stream.flush())
+            .map_err(|e| self.map_timeout(e.into()))
+    }
+
+    /// Writes a typed protocol sentence.
+    pub(crate) fn write_sentence(&mut self, sentence: &ServerSentences) -> crate::Result<()> {
+        self.write_line(&join_sentence(sentence.encode()))
+    }
+
+    /// Reads typed protocol sentences, up to and including the first one for which
+    /// `is_last` returns `true`.
+    ///
+    /// Fails if the server responds with an error, or with a sentence that cannot be decoded.
+    pub(crate) fn read_sentences_until<F>(
+        &mut self,
+        is_last: F,
+    ) -> crate::Result<Vec<ClientSentences>>
+    where
+        F: Fn(&ClientSentences) -> bool,
+    {
+        // No cap on the number of sentences: large devices (e.g. PDUs) can have thousands of
+        // rows. A stalled server blocks indefinitely, unless a read timeout or a deadline
+        // is configured (see `with_read_timeout` and `with_deadline`).
+        let mut sentences = Vec::new();
+        loop {
+            let args = self.read_args()?;
+            let sentence = ClientSentences::decode(args).ok_or(NutError::UnexpectedResponse)?;
+            if let ClientSentences::RespondErr { .. } = sentence {
+                return Err(NutError::from(sentence).into());
+            }
+            let done = is_last(&sentence);
+            sentences.push(sentence);
+            if done {
+                break;
+            }
+        }
+        Ok(sentences)
+    }
+
+    fn read_args(&mut self) -> crate::Result<Vec<String>> {
+        self.apply_deadline()?;
+        Self::parse_line(&mut self.stream, self.config.debug).map_err(|e| self.map_timeout(e))
+    }
+
+    fn parse_line<R: BufRead>(reader: &mut R, debug: bool) -> crate::Result<Vec<String>> {
         let mut raw = String::new();
-        reader.read_line(&mut raw)?;
+        // Reads interrupted by a signal (e.g. mid-record on a slow TLS link) are retried by
+        // `read_line`; other errors, such as timeouts, are reported by the caller.
+        if reader.read_line(&mut raw)? == 0 {
+            return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+        }
         if debug {
             eprint!("DEBUG <- {}", raw);
         }
-        raw = raw.trim_end_matches('\n').to_string(); // Strip off \n
+        raw = raw.trim_end_matches(&['\r', '\n'][..]).to_string(); // Strip off \n or \r\n
 
         // Parse args by splitting whitespace, minding quotes for args with multiple words
-        let args = shell_words::split(&raw)
-            .map_err(|e| NutError::generic(format!("Parsing server response failed: {}", e)))?;
+        let args = split_sentence(&raw)
+            .ok_or_else(|| NutError::generic(format!("Parsing server response failed: {}", raw)))?;
 
         Ok(args)
     }
 
     pub(crate) fn read_response(&mut self) -> crate::Result<Response> {
-        let mut reader = BufReader::new(&mut self.stream);
-        let args = Self::parse_line(&mut reader, self.config.debug)?;
+        let args = self.read_args()?;
         Response::from_args(args)
     }
 
+    /// Sends single-line requests in pipelined batches, and reads their responses.
+    ///
+    /// Error responses are returned in place, as with [`read_responses`](Self::read_responses).
+    pub(crate) fn pipeline_responses(
+        &mut self,
+        queries: &[String],
+    ) -> crate::Result<Vec<crate::Result<Response>>> {
+        let mut responses = Vec::with_capacity(queries.len());
+        for batch in queries.chunks(PIPELINE_BATCH) {
+            self.write_lines(batch)?;
+            responses.extend(self.read_responses(batch.len())?);
+        }
+        Ok(responses)
+    }
+
+    /// Sends `LIST` queries in pipelined batches, and reads their lists.
+    ///
+    /// Errors in a list are returned in place, so every list is read.
+    pub(crate) fn pipeline_lists<'q, Q: AsRef<[&'q str]>>(
+        &mut self,
+        queries: &[Q],
+    ) -> crate::Result<Vec<crate::Result<Vec<Response>>>> {
+        let mut lists = Vec::with_capacity(queries.len());
+        for batch in queries.chunks(PIPELINE_BATCH) {
+            let lines: Vec<String> = batch
+                .iter()
+                .map(|query| Command::List(query.as_ref()).to_string())
+                .collect();
+            self.write_lines(&lines)?;
+            for query in batch {
+                lists.push(self.read_list(query.as_ref()));
+            }
+        }
+        Ok(lists)
+    }
+
+    /// Reads the responses to `count` pipelined requests.
+    ///
+    /// Error responses don't stop the following ones from being read, which leaves the
+    /// stream in a clean state; only a failure to read from the stream is returned early.
+    pub(crate) fn read_responses(
+        &mut self,
+        count: usize,
+    ) -> crate::Result<Vec<crate::Result<Response>>> {
+        let mut responses = Vec::with_capacity(count);
+        for _ in 0..count {
+            let args = self.read_args()?;
+            responses.push(Response::from_args(args));
+        }
+        Ok(responses)
+    }
+
     pub(crate) fn read_plain_response(&mut self) -> crate::Result<String> {
-        let mut reader = BufReader::new(&mut self.stream);
-        let args = Self::parse_line(&mut reader, self.config.debug)?;
+        let args = self.read_args()?;
+        if args.first().map(String::as_str) == Some("ERR") {
+            return Err(Response::from_args(args)
+                .err()
+                .unwrap_or_else(|| NutError::UnexpectedResponse.into()));
+        }
         Ok(args.join(" "))
     }
 
+    pub(crate) fn read_raw_response(&mut self) -> crate::Result<Vec<Vec<String>>> {
+        let mut lines = Vec::new();
+
+        loop {
+            let args = self.read_args()?;
+            let word = args.first().cloned().unwrap_or_default();
+            if word == "ERR" {
+                return Err(Response::from_args(args)
+                    .err()
+                    .unwrap_or_else(|| NutError::UnexpectedResponse.into()));
+            }
+            let is_list = !lines.is_empty() || word == "BEGIN";
+            lines.push(args);
+            if !is_list || word == "END" {
+                break;
+            }
+        }
+
+        Ok(lines)
+    }
+
     pub(crate) fn read_list(&mut self, query: &[&str]) -> crate::Result<Vec<Response>> {
-        let mut reader = BufReader::new(&mut self.stream);
-        let args = Self::parse_line(&mut reader, self.config.debug)?;
+        let mut lines: Vec<Response> = Vec::new();
+        self.read_list_with(query, |resp| {
+            lines.push(resp);
+            Ok(())
+        })?;
+        Ok(lines)
+    }
+
+    /// Reads a list, passing each row to `f` as soon as it is read.
+    ///
+    /// If `f` fails, the rest of the list is still read (but not passed to `f`),
+    /// and the first error is returned.
+    pub(crate) fn read_list_with<F>(&mut self, query: &[&str], mut f: F) -> crate::Result<()>
+    where
+        F: FnMut(Response) -> crate::Result<()>,
+    {
+        let args = self.read_args()?;
 
+        // An error instead of the list (e.g. `ERR UNKNOWN-UPS`) is returned as-is
         Response::from_args(args)?.expect_begin_list(query)?;
-        let mut lines: Vec<Response> = Vec::new();
+        let mut result = Ok(());
 
         loop {
-            let args = Self::parse_line(&mut reader, self.config.debug)?;
+            let args = self.read_args()?;
             let resp = Response::from_args(args)?;
 
             match resp {
                 Response::EndList(_) => {
+                    resp.expect_end_list(query)?;
                     break;
                 }
-                _ => lines.push(resp),
+                _ => {
+                    if result.is_ok() {
+                        result = f(resp);
+                    }
+                }
             }
         }
 
-        Ok(lines)
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{written, MockOutput, MockStream};
+    use crate::{Auth, CommandSchema, ConfigBuilder, DeviceSchema, OwnedCommand, VariableSchema};
+
+    /// A stream whose reads return the given chunks or errors, in order.
+    struct FlakyStream {
+        reads: std::collections::VecDeque<std::io::Result<&'static [u8]>>,
+    }
+
+    impl Read for FlakyStream {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            match self.reads.pop_front() {
+                Some(Ok(chunk)) => {
+                    buf[..chunk.len()].copy_from_slice(chunk);
+                    Ok(chunk.len())
+                }
+                Some(Err(e)) => Err(e),
+                None => Ok(0),
+            }
+        }
+    }
+
+    impl Write for FlakyStream {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// A stream that answers each request as soon as it is written, but fails if more than
+    /// [`PIPELINE_BATCH`] requests are written without reading in between (as a server with
+    /// full buffers would stall).
+    struct BoundedStream<F> {
+        answer: F,
+        request: Vec<u8>,
+        replies: std::collections::VecDeque<u8>,
+        unread: usize,
+    }
+
+    impl<F: FnMut(&str) -> Vec<String>> BoundedStream<F> {
+        fn new(answer: F) -> Self {
+            BoundedStream {
+                answer,
+                request: Vec::new(),
+                replies: Default::default(),
+                unread: 0,
+            }
+        }
+    }
+
+    impl<F> Read for BoundedStream<F> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.unread = 0;
+            self.replies.read(buf)
+        }
+    }
+
+    impl<F: FnMut(&str) -> Vec<String>> Write for BoundedStream<F> {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            for &byte in buf {
+                if byte != b'\n' {
+                    self.request.push(byte);
+                    continue;
+                }
+                self.unread += 1;
+                if self.unread > PIPELINE_BATCH {
+                    return Err(std::io::ErrorKind::WouldBlock.into());
+                }
+                let request = String::from_utf8(std::mem::take(&mut self.request)).unwrap();
+                for reply in (self.answer)(&request) {
+                    self.replies.extend(reply.bytes().chain(Some(b'\n')));
+                }
+            }
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Connects a client to a scripted stream, with the default configuration.
+    ///
+    /// The version probes are answered and left out of the recorded output, so the script only
+    /// holds the responses to the requests of the test.
+    fn mock_conn(lines: &[&str]) -> (Connection, MockOutput) {
+        let mut script = vec!["1.2", "Network UPS Tools upsd 2.8.0"];
+        script.extend_from_slice(lines);
+        let (stream, output) = MockStream::new(&script);
+        let conn = Connection::from_stream(&ConfigBuilder::new().build(), stream).unwrap();
+        output.lock().unwrap().clear();
+        (conn, output)
+    }
+
+    #[test]
+    fn test_from_stream() {
+        let (mut conn, output) =
+            mock_conn(&["BEGIN LIST UPS", "UPS nutdev \"Test UPS\"", "END LIST UPS"]);
+
+        assert_eq!(
+            conn.list_ups().unwrap(),
+            vec![("nutdev".into(), "Test UPS".into())]
+        );
+        assert_eq!(written(&output), "LIST UPS\n");
+        assert_eq!(conn.network_version(), Some("1.2"));
+        assert!(!conn.is_tls());
+        assert!(conn.tcp_stream().is_none());
+        #[cfg(feature = "ssl")]
+        assert!(conn.tls_info().is_none());
+    }
+
+    #[test]
+    fn test_ssl_not_enabled() {
+        let (stream, _) = MockStream::new(&["ERR FEATURE-NOT-CONFIGURED"]);
+        let mut config = ConfigBuilder::new().build();
+        config.ssl = true;
+
+        // Whether the server refuses, or SSL support is not compiled in,
+        // the connection must not carry on in plain text
+        assert!(matches!(
+            Connection::from_stream(&config, stream),
+            Err(ClientError::Nut(NutError::SslNotSupported))
+        ));
+    }
+
+    #[test]
+    #[cfg(feature = "ssl")]
+    fn test_ssl_handshake_failed() {
+        let (stream, output) = MockStream::new(&["OK STARTTLS", "garbage", "1.2"]);
+        let mut config = ConfigBuilder::new()
+            .with_ssl(true)
+            .with_insecure_ssl(true)
+            .build();
+        config.auth = Some(Auth::new("admin".into(), Some("secret".into())));
+
+        assert!(matches!(
+            Connection::from_stream(&config, stream),
+            Err(ClientError::Nut(NutError::SslHandshakeFailed(_)))
+        ));
+        assert!(!written(&output).contains("secret"));
+    }
+
+    #[test]
+    #[cfg(feature = "ssl")]
+    fn test_custom_rustls_config() {
+        // The custom configuration takes precedence over `with_insecure_ssl`,
+        // so the host must have a DNS name for its verifier
+        let (stream, output) = MockStream::new(&["OK STARTTLS"]);
+        let config = ConfigBuilder::new()
+            .with_host(
+                "127.0.0.1:3493"
+                    .parse::<std::net::SocketAddr>()
+                    .unwrap()
+                    .into(),
+            )
+            .with_ssl(true)
+            .with_insecure_ssl(true)
+            .with_rustls_config(std::sync::Arc::new(rustls::ClientConfig::new()))
+            .build();
+
+        assert!(matches!(
+            Connection::from_stream(&config, stream),
+            Err(ClientError::Nut(NutError::SslInvalidHostname))
+        ));
+        assert_eq!(written(&output), "STARTTLS\n");
+    }
+
+    #[test]
+    fn test_manual_login() {
+        let (stream, output) =
+            MockStream::new(&["1.2", "Network UPS Tools upsd 2.8.0", "OK", "OK"]);
+        let config = ConfigBuilder::new()
+            .with_auth(Some(Auth::new("admin".into(), Some("secret".into()))))
+            .with_auto_login(false)
+            .build();
+        let mut conn = Connection::from_stream(&config, stream).unwrap();
+        assert_eq!(written(&output), "NETVER\nVER\n");
+
+        conn.set_username("upsmon").unwrap();
+        conn.set_password("hunter2").unwrap();
+        assert_eq!(
+            written(&output),
+            "NETVER\nVER\nUSERNAME upsmon\nPASSWORD hunter2\n"
+        );
+    }
+
+    #[test]
+    fn test_verify_auth() {
+        let (stream, _) = MockStream::new(&[
+            "1.2",
+            "Network UPS Tools upsd 2.8.0",
+            "OK",
+            "ERR INVALID-PASSWORD",
+        ]);
+        let config = ConfigBuilder::new()
+            .with_auth(Some(Auth::new("upsmon".into(), Some("".into()))))
+            .with_auto_login(false)
+            .build();
+        let mut conn = Connection::from_stream(&config, stream).unwrap();
+        assert!(matches!(
+            conn.verify_auth(),
+            Err(ClientError::Nut(NutError::InvalidPassword))
+        ));
+
+        let (mut conn, _) = mock_conn(&[]);
+        assert!(matches!(
+            conn.verify_auth(),
+            Err(ClientError::Nut(NutError::UsernameRequired))
+        ));
+    }
+
+    #[test]
+    fn test_login_on_access_denied() {
+        let (stream, output) = MockStream::new(&[
+            "OK",
+            "OK",
+            "1.2",
+            "Network UPS Tools upsd 2.8.0",
+            "ERR ACCESS-DENIED",
+            "OK",
+            "VAR nutdev ups.status \"OL\"",
+        ]);
+        let config = ConfigBuilder::new()
+            .with_auth(Some(Auth::new("admin".into(), Some("secret".into()))))
+            .with_auto_login_on_access_denied(true)
+            .build();
+        let mut conn = Connection::from_stream(&config, stream).unwrap();
+
+        assert_eq!(conn.get_var("nutdev", "ups.status").unwrap().value(), "OL");
+        assert_eq!(
+            written(&output),
+            "USERNAME admin\nPASSWORD secret\nNETVER\nVER\nGET VAR nutdev ups.status\n\
+             LOGIN nutdev\nGET VAR nutdev ups.status\n"
+        );
+
+        // Disabled by default
+        let (stream, output) = MockStream::new(&[
+            "OK",
+            "OK",
+            "1.2",
+            "Network UPS Tools upsd 2.8.0",
+            "ERR ACCESS-DENIED",
+        ]);
+        let config = ConfigBuilder::new()
+            .with_auth(Some(Auth::new("admin".into(), Some("secret".into()))))
+            .build();
+        let mut conn = Connection::from_stream(&config, stream).unwrap();
+
+        assert!(matches!(
+            conn.list_vars("nutdev"),
+            Err(ClientError::Nut(NutError::AccessDenied))
+        ));
+        assert!(!written(&output).contains("LOGIN"));
+    }
+
+    #[test]
+    fn test_login_on_access_denied_failed() {
+        let (stream, output) = MockStream::new(&[
+            "OK",
+            "OK",
+            "1.2",
+            "Network UPS Tools upsd 2.8.0",
+            "ERR ACCESS-DENIED",
+            "ERR ALREADY-LOGGED-IN",
+        ]);
+        let config = ConfigBuilder::new()
+            .with_auth(Some(Auth::new("admin".into(), Some("secret".into()))))
+            .with_auto_login_on_access_denied(true)
+            .build();
+        let mut conn = Connection::from_stream(&config, stream).unwrap();
+
+        // The error of LOGIN tells why the request was not retried
+        assert!(matches!(
+            conn.get_var("nutdev", "ups.status"),
+            Err(ClientError::Nut(NutError::AlreadyLoggedIn))
+        ));
+        assert_eq!(
+            written(&output),
+            "USERNAME admin\nPASSWORD secret\nNETVER\nVER\nGET VAR nutdev ups.status\n\
+             LOGIN nutdev\n"
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "ssl")]
+    fn test_tls_info() {
+        let (addr, server) = crate::test_util::tls_server();
+        let config = ConfigBuilder::new()
+            .with_host(addr.into())
+            .with_ssl(true)
+            .with_insecure_ssl(true)
+            .build();
+        let conn = Connection::new(&config).unwrap();
+        let _stream = server.join().unwrap();
+
+        assert!(conn.is_tls());
+        let info = conn.tls_info().unwrap();
+        assert_eq!(info.protocol_version, rustls::ProtocolVersion::TLSv1_3);
+    }
+
+    #[test]
+    fn test_login_without_username() {
+        let (stream, output) = MockStream::new(&[]);
+        let config = ConfigBuilder::new()
+            .with_auth(Some(Auth::new(String::new(), Some("secret".into()))))
+            .build();
+
+        assert!(matches!(
+            Connection::from_stream(&config, stream),
+            Err(ClientError::Nut(NutError::UsernameRequired))
+        ));
+        assert_eq!(written(&output), "");
+    }
+
+    #[test]
+    fn test_login_before_probe() {
+        let (stream, output) =
+            MockStream::new(&["OK", "OK", "1.2", "Network UPS Tools upsd 2.8.0"]);
+        let config = ConfigBuilder::new()
+            .with_auth(Some(Auth::new("upsmon".into(), Some("hunter2".into()))))
+            .build();
+        let conn = Connection::from_stream(&config, stream).unwrap();
+        assert_eq!(
+            written(&output),
+            "USERNAME upsmon\nPASSWORD hunter2\nNETVER\nVER\n"
+        );
+        assert_eq!(conn.network_version(), Some("1.2"));
+    }
+
+    #[test]
+    fn test_version_probe() {
+        let (stream, output) = MockStream::new(&["1.3", "Network UPS Tools upsd 2.8.1"]);
+        let conn = Connection::from_stream(&ConfigBuilder::new().build(), stream).unwrap();
+        assert_eq!(written(&output), "NETVER\nVER\n");
+        assert_eq!(conn.network_version(), Some("1.3"));
+        assert!(conn.server_version().unwrap().is_at_least((2, 8, 1)));
+
+        let (stream, output) = MockStream::new(&["ERR UNKNOWN-COMMAND", "ERR UNKNOWN-COMMAND"]);
+        let conn = Connection::from_stream(&ConfigBuilder::new().build(), stream).unwrap();
+        assert_eq!(written(&output), "NETVER\nVER\n");
+        assert_eq!(conn.network_version(), None);
+        assert_eq!(conn.server_version(), None);
+    }
+
+    #[test]
+    fn test_no_version_probe() {
+        let (stream, output) = MockStream::new(&[]);
+        let config = ConfigBuilder::new().with_version_probe(false).build();
+        let conn = Connection::from_stream(&config, stream).unwrap();
+        assert_eq!(written(&output), "");
+        assert_eq!(conn.network_version(), None);
+        assert_eq!(conn.server_version(), None);
+    }
+
+    #[test]
+    fn test_get_var_data_stale() {
+        let (mut conn, _) = mock_conn(&["ERR DATA-STALE"]);
+
+        assert!(matches!(
+            conn.get_var("nutdev", "ups.status"),
+            Err(ClientError::Nut(NutError::DataStale))
+        ));
+    }
+
+    #[test]
+    fn test_read_list_unknown_ups() {
+        let (mut conn, _) = mock_conn(&["ERR UNKNOWN-UPS", "1.2"]);
+
+        assert!(matches!(
+            conn.list_vars("typo"),
+            Err(ClientError::Nut(NutError::UnknownUps))
+        ));
+        assert_eq!(conn.get_network_version().unwrap(), "1.2");
+    }
+
+    #[test]
+    fn test_read_list_aborted() {
+        let (mut conn, _) = mock_conn(&[
+            "BEGIN LIST VAR nutdev",
+            "VAR nutdev battery.charge \"100\"",
+            "ERR DATA-STALE",
+            "1.2",
+        ]);
+
+        assert!(matches!(
+            conn.list_vars("nutdev"),
+            Err(ClientError::Nut(NutError::DataStale))
+        ));
+        assert_eq!(conn.get_network_version().unwrap(), "1.2");
+    }
+
+    #[test]
+    fn test_primary() {
+        // The server version probed when connecting is reused
+        let (mut conn, output) = mock_conn(&["OK PRIMARY-GRANTED", "OK PRIMARY-GRANTED"]);
+        conn.primary("nutdev").unwrap();
+        conn.primary("nutdev").unwrap();
+        assert_eq!(written(&output), "PRIMARY nutdev\nPRIMARY nutdev\n");
+
+        let (stream, output) = MockStream::new(&[
+            "Network UPS Tools upsd 2.7.4 - http://www.networkupstools.org/",
+            "OK",
+            "OK",
+        ]);
+        let config = ConfigBuilder::new().with_version_probe(false).build();
+        let mut conn = Connection::from_stream(&config, stream).unwrap();
+        conn.primary("nutdev").unwrap();
+        conn.primary("nutdev").unwrap();
+        assert_eq!(written(&output), "VER\nMASTER nutdev\nMASTER nutdev\n");
+    }
+
+    #[test]
+    #[cfg(feature = "write")]
+    fn test_delayed_commands() {
+        let (mut conn, output) = mock_conn(&["OK", "OK"]);
+
+        conn.shutdown_return("nutdev", std::time::Duration::from_millis(30_500))
+            .unwrap();
+        conn.load_off_delay("nutdev", std::time::Duration::from_secs(120))
+            .unwrap();
+        assert_eq!(
+            written(&output),
+            "INSTCMD nutdev shutdown.return 31\nINSTCMD nutdev load.off.delay 120\n"
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "write")]
+    fn test_delayed_commands_rounding() {
+        let (mut conn, output) = mock_conn(&["OK", "OK", "OK"]);
+
+        // Sub-second delays are rounded up, rather than sent as an immediate shutdown
+        conn.load_off_delay("nutdev", std::time::Duration::from_millis(500))
+            .unwrap();
+        // A zero delay is the only way to request one
+        conn.shutdown_return("nutdev", std::time::Duration::from_secs(0))
+            .unwrap();
+        conn.load_off_delay("nutdev", std::time::Duration::default())
+            .unwrap();
+        assert_eq!(
+            written(&output),
+            "INSTCMD nutdev load.off.delay 1\nINSTCMD nutdev shutdown.return 0\n\
+             INSTCMD nutdev load.off.delay 0\n"
+        );
+    }
+
+    #[test]
+    fn test_full_report() {
+        let (mut conn, _) = mock_conn(&[
+            "Network UPS Tools upsd 2.8.0",
+            "1.2",
+            "BEGIN LIST UPS",
+            "UPS nutdev \"Test UPS\"",
+            "END LIST UPS",
+            "NUMLOGINS nutdev 1",
+            "BEGIN LIST VAR nutdev",
+            "END LIST VAR nutdev",
+            "BEGIN LIST CMD nutdev",
+            "END LIST CMD nutdev",
+        ]);
+
+        let report = conn.full_report().unwrap();
+        assert_eq!(report.server_version, "Network UPS Tools upsd 2.8.0");
+        assert_eq!(report.network_version, "1.2");
+        assert_eq!(report.devices.len(), 1);
+        assert_eq!(report.devices[0].description, "Test UPS");
+        assert_eq!(report.devices[0].num_logins, 1);
+        assert_eq!(report.devices[0].schema.ups_name, "nutdev");
+    }
+
+    #[test]
+    fn test_var_constraints() {
+        let (mut conn, output) = mock_conn(&[
+            "TYPE nutdev input.transfer.low RW RANGE",
+            "BEGIN LIST RANGE nutdev input.transfer.low",
+            "RANGE nutdev input.transfer.low \"90\" \"105\"",
+            "END LIST RANGE nutdev input.transfer.low",
+            "TYPE nutdev ups.id RW STRING:16",
+        ]);
+
+        let constraints = conn
+            .var_constraints("nutdev", "input.transfer.low")
+            .unwrap();
+        assert!(constraints.definition.is_range());
+        assert_eq!(
+            constraints.ranges,
+            vec![crate::VariableRange("90".into(), "105".into())]
+        );
+        assert!(constraints.enums.is_empty());
+
+        let constraints = conn.var_constraints("nutdev", "ups.id").unwrap();
+        assert!(constraints.ranges.is_empty() && constraints.enums.is_empty());
+        assert_eq!(
+            written(&output),
+            "GET TYPE nutdev input.transfer.low\n\
+             LIST RANGE nutdev input.transfer.low\nGET TYPE nutdev ups.id\n"
+        );
+    }
+
+    #[test]
+    fn test_read_only_var_constraints() {
+        // Ranges and enums are not limited to writable variables
+        let (mut conn, output) = mock_conn(&[
+            "TYPE nutdev input.voltage RANGE NUMBER",
+            "BEGIN LIST RANGE nutdev input.voltage",
+            "RANGE nutdev input.voltage \"200\" \"250\"",
+            "END LIST RANGE nutdev input.voltage",
+        ]);
+
+        let constraints = conn.var_constraints("nutdev", "input.voltage").unwrap();
+        assert!(!constraints.definition.is_mutable());
+        assert_eq!(
+            constraints.ranges,
+            vec![crate::VariableRange("200".into(), "250".into())]
+        );
+        assert_eq!(
+            written(&output),
+            "GET TYPE nutdev input.voltage\nLIST RANGE nutdev input.voltage\n"
+        );
+    }
+
+    #[test]
+    fn test_try_get_var() {
+        let (mut conn, _) = mock_conn(&[
+            "VAR nutdev ups.load \"23\"",
+            "ERR VAR-NOT-SUPPORTED",
+            "ERR UNKNOWN-UPS",
+        ]);
+
+        assert_eq!(
+            conn.try_get_var("nutdev", "ups.load").unwrap(),
+            Some(Variable::Other(("ups.load".into(), "23".into())))
+        );
+        assert_eq!(
+            conn.try_get_var("nutdev", "ambient.temperature").unwrap(),
+            None
+        );
+        assert!(matches!(
+            conn.try_get_var("other", "ups.load"),
+            Err(ClientError::Nut(NutError::UnknownUps))
+        ));
+    }
+
+    #[test]
+    fn test_list_all_enums() {
+        let (mut conn, output) = mock_conn(&[
+            "BEGIN LIST VAR nutdev",
+            "VAR nutdev ups.load \"23\"",
+            "VAR nutdev input.sensitivity \"normal\"",
+            "END LIST VAR nutdev",
+            "TYPE nutdev ups.load NUMBER",
+            "TYPE nutdev input.sensitivity RW ENUM",
+            "BEGIN LIST ENUM nutdev input.sensitivity",
+            "ENUM nutdev input.sensitivity \"low\"",
+            "ENUM nutdev input.sensitivity \"normal\"",
+            "END LIST ENUM nutdev input.sensitivity",
+        ]);
+
+        let enums = conn.list_all_enums("nutdev").unwrap();
+        assert_eq!(enums.len(), 1);
+        assert_eq!(enums["input.sensitivity"], vec!["low", "normal"]);
+        assert_eq!(
+            written(&output),
+            "LIST VAR nutdev\n\
+            GET TYPE nutdev ups.load\nGET TYPE nutdev input.sensitivity\n\
+            LIST ENUM nutdev input.sensitivity\n"
+        );
+    }
+
+    #[test]
+    fn test_list_all_enums_batches() {
+        let stream = BoundedStream::new(|request: &str| {
+            let args: Vec<&str> = request.split(' ').collect();
+            match args.as_slice() {
+                ["NETVER"] => vec!["1.2".to_string()],
+                ["VER"] => vec!["Network UPS Tools upsd 2.8.0".to_string()],
+                ["LIST", "VAR", ups] => {
+                    let mut lines = vec![format!("BEGIN LIST VAR {}", ups)];
+                    lines.extend((0..100).map(|i| format!("VAR {} var{} \"0\"", ups, i)));
+                    lines.push(format!("END LIST VAR {}", ups));
+                    lines
+                }
+                ["GET", "TYPE", ups, var] => {
+                    let enum_var = var[3..].parse::<usize>().unwrap() % 2 == 0;
+                    let kind = if enum_var { "ENUM" } else { "NUMBER" };
+                    vec![format!("TYPE {} {} RW {}", ups, var, kind)]
+                }
+                ["LIST", "ENUM", ups, var] => vec![
+                    format!("BEGIN LIST ENUM {} {}", ups, var),
+                    format!("ENUM {} {} \"{}\"", ups, var, var),
+                    format!("END LIST ENUM {} {}", ups, var),
+                ],
+                _ => vec!["ERR UNKNOWN-COMMAND".to_string()],
+            }
+        });
+        let mut conn = Connection::from_stream(&ConfigBuilder::new().build(), stream).unwrap();
+
+        let enums = conn.list_all_enums("nutdev").unwrap();
+        assert_eq!(enums.len(), 50);
+        assert_eq!(enums["var42"], vec!["var42"]);
+    }
+
+    #[test]
+    fn test_schema() {
+        let (mut conn, output) = mock_conn(&[
+            "BEGIN LIST VAR nutdev",
+            "VAR nutdev ups.delay.shutdown \"20\"",
+            "VAR nutdev input.transfer.low \"100\"",
+            "END LIST VAR nutdev",
+            "DESC nutdev ups.delay.shutdown \"Interval to wait after shutdown\"",
+            "TYPE nutdev ups.delay.shutdown RW STRING:10",
+            "DESC nutdev input.transfer.low \"Low voltage transfer point\"",
+            "TYPE nutdev input.transfer.low RW RANGE",
+            "BEGIN LIST RANGE nutdev input.transfer.low",
+            "RANGE nutdev input.transfer.low \"80\" \"90\"",
+            "RANGE nutdev input.transfer.low \"95\" \"105\"",
+            "END LIST RANGE nutdev input.transfer.low",
+            "BEGIN LIST CMD nutdev",
+            "CMD nutdev beeper.on",
+            "END LIST CMD nutdev",
+            "CMDDESC nutdev beeper.on \"Enable the beeper\"",
+        ]);
+
+        assert_eq!(
+            conn.schema("nutdev").unwrap(),
+            DeviceSchema {
+                ups_name: "nutdev".into(),
+                variables: vec![
+                    VariableSchema {
+                        name: "ups.delay.shutdown".into(),
+                        value: "20".into(),
+                        description: "Interval to wait after shutdown".into(),
+                        types: vec!["RW".into(), "STRING:10".into()],
+                        enum_values: vec![],
+                        ranges: vec![],
+                    },
+                    VariableSchema {
+                        name: "input.transfer.low".into(),
+                        value: "100".into(),
+                        description: "Low voltage transfer point".into(),
+                        types: vec!["RW".into(), "RANGE".into()],
+                        enum_values: vec![],
+                        ranges: vec![
+                            crate::VariableRange("80".into(), "90".into()),
+                            crate::VariableRange("95".into(), "105".into()),
+                        ],
+                    },
+                ],
+                commands: vec![CommandSchema {
+                    name: "beeper.on".into(),
+                    description: "Enable the beeper".into(),
+                }],
+            }
+        );
+        assert!(written(&output).contains("LIST RANGE nutdev input.transfer.low\n"));
+    }
+
+    #[test]
+    fn test_bind_address() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let server_addr = listener.local_addr().unwrap();
+        let bind_addr = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+
+        let server = std::thread::spawn(move || {
+            let (mut stream, peer_addr) = listener.accept().unwrap();
+            answer_probes(&mut stream, "1.2");
+            peer_addr
+        });
+
+        let config = ConfigBuilder::new()
+            .with_host(server_addr.into())
+            .with_bind_address(bind_addr)
+            .build();
+        let conn = Connection::new(&config).unwrap();
+        assert_eq!(conn.network_version(), Some("1.2"));
+        assert_eq!(server.join().unwrap(), bind_addr);
+    }
+
+    /// Answers the version probes (`NETVER` and `VER`) sent when connecting.
+    fn answer_probes(stream: &mut TcpStream, network_version: &str) {
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        for response in [network_version, "Network UPS Tools upsd 2.8.0"] {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            writeln!(stream, "{}", response).unwrap();
+        }
+    }
+
+    /// Spawns a server that accepts one connection and answers the version probes, then
+    /// returns the stream (to keep it open).
+    fn probe_server() -> (std::net::SocketAddr, std::thread::JoinHandle<TcpStream>) {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            answer_probes(&mut stream, "1.2");
+            stream
+        });
+        (addr, server)
+    }
+
+    #[test]
+    fn test_tcp_stream_ttl() {
+        let (addr, server) = probe_server();
+        let config = ConfigBuilder::new().with_host(addr.into()).build();
+        let conn = Connection::new(&config).unwrap();
+        let _stream = server.join().unwrap();
+
+        let socket = conn.tcp_stream().unwrap();
+        assert_eq!(socket.peer_addr().unwrap(), addr);
+        socket.set_ttl(32).unwrap();
+        assert_eq!(socket.ttl().unwrap(), 32);
+    }
+
+    #[test]
+    fn test_nodelay() {
+        let (addr, server) = probe_server();
+        let config = ConfigBuilder::new().with_host(addr.into()).build();
+        let conn = Connection::new(&config).unwrap();
+        let _stream = server.join().unwrap();
+        assert!(conn.tcp_stream().unwrap().nodelay().unwrap());
+
+        let (addr, server) = probe_server();
+        let config = ConfigBuilder::new()
+            .with_host(addr.into())
+            .with_tcp_nodelay(false)
+            .build();
+        let conn = Connection::new(&config).unwrap();
+        let _stream = server.join().unwrap();
+        assert!(!conn.tcp_stream().unwrap().nodelay().unwrap());
+    }
+
+    #[test]
+    fn test_fallback_hosts() {
+        // Nothing listens on the primary host
+        let primary_addr = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let fallback_addr = listener.local_addr().unwrap();
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            answer_probes(&mut stream, "1.3");
+        });
+
+        let config = ConfigBuilder::new()
+            .with_host(primary_addr.into())
+            .with_fallback_hosts(vec![fallback_addr.into()])
+            .build();
+        let conn = Connection::new(&config).unwrap();
+        assert_eq!(conn.network_version(), Some("1.3"));
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_list_ups_unbounded() {
+        // Lists are only bounded by the timeouts, not by a number of rows
+        let mut lines = vec![String::from("BEGIN LIST UPS")];
+        lines.extend((0..1500).map(|i| format!("UPS pdu{} \"Outlet {}\"", i, i)));
+        lines.push(String::from("END LIST UPS"));
+        let lines: Vec<&str> = lines.iter().map(String::as_str).collect();
+        let (mut conn, _) = mock_conn(&lines);
+
+        let devices = conn.list_ups().unwrap();
+        assert_eq!(devices.len(), 1500);
+        assert_eq!(devices[1499].0, "pdu1499");
+    }
+
+    #[test]
+    fn test_capabilities() {
+        let (stream, output) = MockStream::new(&[
+            "1.3",
+            "Network UPS Tools upsd 2.8.0 - https://www.networkupstools.org/",
+            "Commands: HELP VER GET LIST SET INSTCMD LOGIN LOGOUT USERNAME PASSWORD STARTTLS",
+        ]);
+        let config = ConfigBuilder::new().build();
+        let mut conn = Connection::from_stream(&config, stream).unwrap();
+
+        let caps = conn.capabilities().unwrap();
+        assert!(caps.supports_primary);
+        assert!(caps.supports_range);
+        assert!(caps.commands.contains(&"STARTTLS".to_string()));
+        // Cached afterwards
+        assert_eq!(conn.capabilities().unwrap(), caps);
+        // The server version probed when connecting is reused
+        assert_eq!(written(&output), "NETVER\nVER\nHELP\n");
+    }
+
+    #[test]
+    fn test_read_only() {
+        let (stream, output) = MockStream::new(&["1.2", "Network UPS Tools upsd 2.8.0"]);
+        let config = ConfigBuilder::new().with_read_only(true).build();
+        let mut conn = Connection::from_stream(&config, stream).unwrap();
+
+        assert!(matches!(
+            conn.raw("INSTCMD nutdev load.off"),
+            Err(ClientError::Nut(NutError::ReadOnly))
+        ));
+        #[cfg(feature = "write")]
+        assert!(matches!(
+            conn.set_var("nutdev", "ups.id", "Rack 2"),
+            Err(ClientError::Nut(NutError::ReadOnly))
+        ));
+        assert_eq!(written(&output), "NETVER\nVER\n");
+    }
+
+    #[test]
+    fn test_raw() {
+        let (mut conn, output) = mock_conn(&[
+            "NUMLOGINS nutdev 1",
+            "BEGIN LIST CLIENT nutdev",
+            "CLIENT nutdev 127.0.0.1",
+            "CLIENT nutdev ::1",
+            "END LIST CLIENT nutdev",
+            "ERR UNKNOWN-UPS",
+        ]);
+
+        assert_eq!(
+            conn.raw("GET NUMLOGINS nutdev").unwrap(),
+            vec![vec!["NUMLOGINS", "nutdev", "1"]]
+        );
+        assert_eq!(
+            conn.raw("LIST CLIENT nutdev").unwrap(),
+            vec![
+                vec!["BEGIN", "LIST", "CLIENT", "nutdev"],
+                vec!["CLIENT", "nutdev", "127.0.0.1"],
+                vec!["CLIENT", "nutdev", "::1"],
+                vec!["END", "LIST", "CLIENT", "nutdev"],
+            ]
+        );
+        assert!(matches!(
+            conn.raw("GET NUMLOGINS upsdev"),
+            Err(ClientError::Nut(NutError::UnknownUps))
+        ));
+        assert_eq!(
+            written(&output),
+            "GET NUMLOGINS nutdev\nLIST CLIENT nutdev\nGET NUMLOGINS upsdev\n"
+        );
+    }
+
+    #[test]
+    fn test_raw_line_break() {
+        let (mut conn, output) = mock_conn(&[]);
+
+        assert!(conn.raw("GET NUMLOGINS nutdev\nFSD nutdev").is_err());
+        assert!(conn.raw("GET NUMLOGINS nutdev\rFSD nutdev").is_err());
+        assert_eq!(written(&output), "");
+    }
+
+    #[test]
+    fn test_send_command() {
+        let (mut conn, output) = mock_conn(&[
+            "BEGIN LIST VAR nutdev",
+            "VAR nutdev ups.status \"OL\"",
+            "END LIST VAR nutdev",
+            "DESC nutdev ups.status \"UPS status\"",
+        ]);
+
+        let rows = conn
+            .send_command(&OwnedCommand::list(vec!["VAR".into(), "nutdev".into()]))
+            .unwrap();
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows[1], vec!["VAR", "nutdev", "ups.status", "OL"]);
+        let cmd = OwnedCommand::get(vec!["DESC".into(), "nutdev".into(), "ups.status".into()]);
+        assert_eq!(
+            conn.send_command(&cmd).unwrap(),
+            vec![vec!["DESC", "nutdev", "ups.status", "UPS status"]]
+        );
+        assert_eq!(
+            written(&output),
+            "LIST VAR nutdev\nGET DESC nutdev ups.status\n"
+        );
+    }
+
+    #[test]
+    fn test_get_alarms() {
+        let (mut conn, output) = mock_conn(&[
+            "VAR nutdev ups.alarm \"[Replace battery!] [Overload!]\"",
+            "ERR VAR-NOT-SUPPORTED",
+        ]);
+
+        assert_eq!(
+            conn.get_alarms("nutdev").unwrap(),
+            vec!["Replace battery!", "Overload!"]
+        );
+        assert!(conn.get_alarms("nutdev").unwrap().is_empty());
+        assert_eq!(
+            written(&output),
+            "GET VAR nutdev ups.alarm\nGET VAR nutdev ups.alarm\n"
+        );
+    }
+
+    #[test]
+    fn test_ping_latency() {
+        let (mut conn, output) = mock_conn(&["1.2"]);
+
+        assert!(conn.ping_latency().unwrap() < std::time::Duration::from_secs(1));
+        assert!(conn.ping_latency().is_err());
+        assert_eq!(written(&output), "NETVER\nNETVER\n");
+    }
+
+    #[test]
+    fn test_get_num_logins_named() {
+        let (mut conn, output) = mock_conn(&["NUMLOGINS nutdev 3"]);
+
+        assert_eq!(
+            conn.get_num_logins_named("nutdev").unwrap(),
+            ("nutdev".to_string(), 3)
+        );
+        assert_eq!(written(&output), "GET NUMLOGINS nutdev\n");
+    }
+
+    #[test]
+    fn test_is_primary_connected() {
+        let (mut conn, output) = mock_conn(&[
+            "NUMLOGINS nutdev 2",
+            "BEGIN LIST CLIENT nutdev",
+            "CLIENT nutdev 192.168.1.20",
+            "CLIENT nutdev ::1",
+            "END LIST CLIENT nutdev",
+            "NUMLOGINS nutdev 1",
+            "BEGIN LIST CLIENT nutdev",
+            "CLIENT nutdev 192.168.1.20",
+            "END LIST CLIENT nutdev",
+            "NUMLOGINS nutdev 0",
+        ]);
+
+        assert!(conn.is_primary_connected("nutdev").unwrap());
+        assert!(!conn.is_primary_connected("nutdev").unwrap());
+        assert!(!conn.is_primary_connected("nutdev").unwrap());
+        assert_eq!(
+            written(&output),
+            "GET NUMLOGINS nutdev\nLIST CLIENT nutdev\n\
+             GET NUMLOGINS nutdev\nLIST CLIENT nutdev\nGET NUMLOGINS nutdev\n"
+        );
+    }
+
+    #[test]
+    fn test_list_ups() {
+        let (mut conn, output) = mock_conn(&[
+            "BEGIN LIST UPS",
+            "UPS nutdev1 \"First UPS\"",
+            "UPS nutdev2 \"Second UPS\"",
+            "END LIST UPS",
+            "ERR ACCESS-DENIED",
+        ]);
+
+        assert_eq!(
+            conn.list_ups().unwrap(),
+            vec![
+                ("nutdev1".to_string(), "First UPS".to_string()),
+                ("nutdev2".to_string(), "Second UPS".to_string()),
+            ]
+        );
+        assert!(matches!(
+            conn.list_ups(),
+            Err(ClientError::Nut(NutError::AccessDenied))
+        ));
+        assert_eq!(written(&output), "LIST UPS\nLIST UPS\n");
+    }
+
+    #[test]
+    fn test_iter_all_vars_list_ups_error() {
+        let (mut conn, output) = mock_conn(&["ERR ACCESS-DENIED"]);
+
+        let mut vars = conn.iter_all_vars();
+        assert!(matches!(
+            vars.next(),
+            Some(Err(ClientError::Nut(NutError::AccessDenied)))
+        ));
+        assert!(vars.next().is_none());
+        assert_eq!(written(&output), "LIST UPS\n");
+    }
+
+    #[test]
+    fn test_iter_all_vars_list_var_error() {
+        let (mut conn, output) = mock_conn(&[
+            "BEGIN LIST UPS",
+            "UPS nutdev1 \"First UPS\"",
+            "UPS nutdev2 \"Second UPS\"",
+            "UPS nutdev3 \"Third UPS\"",
+            "END LIST UPS",
+            "BEGIN LIST VAR nutdev1",
+            "VAR nutdev1 ups.status \"OL\"",
+            "END LIST VAR nutdev1",
+            "ERR DRIVER-NOT-CONNECTED",
+        ]);
+
+        let mut vars = conn.iter_all_vars();
+        let (ups_name, var) = vars.next().unwrap().unwrap();
+        assert_eq!(ups_name, "nutdev1");
+        assert_eq!(var.name(), "ups.status");
+        assert!(matches!(vars.next(), Some(Err(_))));
+        assert!(vars.next().is_none());
+        assert_eq!(
+            written(&output),
+            "LIST UPS\nLIST VAR nutdev1\nLIST VAR nutdev2\n"
+        );
+    }
+
+    #[test]
+    fn test_list_editable() {
+        let (mut conn, output) = mock_conn(&[
+            "BEGIN LIST RW nutdev",
+            "RW nutdev input.sensitivity \"normal\"",
+            "RW nutdev input.transfer.low \"95\"",
+            "RW nutdev ups.id \"Rack 1\"",
+            "END LIST RW nutdev",
+            "TYPE nutdev input.sensitivity RW ENUM",
+            "TYPE nutdev input.transfer.low RW RANGE",
+            "TYPE nutdev ups.id RW STRING:10",
+            "BEGIN LIST ENUM nutdev input.sensitivity",
+            "ENUM nutdev input.sensitivity \"low\"",
+            "ENUM nutdev input.sensitivity \"normal\"",
+            "END LIST ENUM nutdev input.sensitivity",
+            "BEGIN LIST RANGE nutdev input.transfer.low",
+            "RANGE nutdev input.transfer.low \"80\" \"90\"",
+            "RANGE nutdev input.transfer.low \"95\" \"105\"",
+            "END LIST RANGE nutdev input.transfer.low",
+        ]);
+
+        let editable = conn.list_editable("nutdev").unwrap();
+        assert_eq!(editable.len(), 3);
+        assert_eq!(editable[0].name, "input.sensitivity");
+        assert_eq!(editable[0].value, "normal");
+        assert_eq!(editable[0].enum_values, vec!["low", "normal"]);
+        assert!(editable[0].ranges.is_empty());
+        assert_eq!(
+            editable[1].ranges,
+            vec![
+                crate::VariableRange("80".into(), "90".into()),
+                crate::VariableRange("95".into(), "105".into()),
+            ]
+        );
+        assert!(editable[1].enum_values.is_empty());
+        assert_eq!(editable[2].value, "Rack 1");
+        assert!(editable[2].enum_values.is_empty() && editable[2].ranges.is_empty());
+        // The types, then the lists, are each requested at once
+        assert_eq!(
+            written(&output),
+            "LIST RW nutdev\n\
+            GET TYPE nutdev input.sensitivity\nGET TYPE nutdev input.transfer.low\n\
+            GET TYPE nutdev ups.id\n\
+            LIST ENUM nutdev input.sensitivity\nLIST RANGE nutdev input.transfer.low\n"
+        );
+    }
+
+    #[test]
+    fn test_list_editable_error() {
+        let (mut conn, output) = mock_conn(&[
+            "BEGIN LIST RW nutdev",
+            "RW nutdev input.sensitivity \"normal\"",
+            "RW nutdev ups.id \"Rack 1\"",
+            "END LIST RW nutdev",
+            "ERR VAR-NOT-SUPPORTED",
+            "TYPE nutdev ups.id RW STRING:10",
+            "NUMLOGINS nutdev 1",
+        ]);
+
+        assert!(matches!(
+            conn.list_editable("nutdev"),
+            Err(ClientError::Nut(NutError::VarNotSupported))
+        ));
+        // Every pipelined response was read, so the connection is still usable
+        assert_eq!(conn.get_num_logins("nutdev").unwrap(), 1);
+        assert_eq!(
+            written(&output),
+            "LIST RW nutdev\n\
+            GET TYPE nutdev input.sensitivity\nGET TYPE nutdev ups.id\n\
+            GET NUMLOGINS nutdev\n"
+        );
+    }
+
+    #[test]
+    fn test_list_all_clients() {
+        let (mut conn, output) = mock_conn(&[
+            "BEGIN LIST UPS",
+            "UPS nutdev1 \"First UPS\"",
+            "UPS nutdev2 \"Second UPS\"",
+            "UPS nutdev3 \"Third UPS\"",
+            "END LIST UPS",
+            "BEGIN LIST CLIENT nutdev1",
+            "CLIENT nutdev1 127.0.0.1",
+            "CLIENT nutdev1 ::1",
+            "END LIST CLIENT nutdev1",
+            "BEGIN LIST CLIENT nutdev2",
+            "END LIST CLIENT nutdev2",
+            "BEGIN LIST CLIENT nutdev3",
+            "CLIENT nutdev3 192.168.1.20",
+            "END LIST CLIENT nutdev3",
+        ]);
+
+        assert_eq!(
+            conn.list_all_clients().unwrap(),
+            vec![
+                ("nutdev1".to_string(), "127.0.0.1".to_string()),
+                ("nutdev1".to_string(), "::1".to_string()),
+                ("nutdev3".to_string(), "192.168.1.20".to_string()),
+            ]
+        );
+        assert_eq!(
+            written(&output),
+            "LIST UPS\nLIST CLIENT nutdev1\nLIST CLIENT nutdev2\nLIST CLIENT nutdev3\n"
+        );
+    }
+
+    #[test]
+    fn test_list_all_clients_error() {
+        let (mut conn, output) = mock_conn(&[
+            "BEGIN LIST UPS",
+            "UPS nutdev1 \"First UPS\"",
+            "UPS nutdev2 \"Second UPS\"",
+            "UPS nutdev3 \"Third UPS\"",
+            "END LIST UPS",
+            "BEGIN LIST CLIENT nutdev1",
+            "CLIENT nutdev1 127.0.0.1",
+            "END LIST CLIENT nutdev1",
+            "ERR ACCESS-DENIED",
+        ]);
+
+        assert!(matches!(
+            conn.list_all_clients(),
+            Err(ClientError::Nut(NutError::AccessDenied))
+        ));
+        assert_eq!(
+            written(&output),
+            "LIST UPS\nLIST CLIENT nutdev1\nLIST CLIENT nutdev2\n"
+        );
+    }
+
+    #[test]
+    fn test_list_ups_filtered() {
+        let (mut conn, output) = mock_conn(&[
+            "BEGIN LIST UPS",
+            "UPS pdu1 \"Rack PDU\"",
+            "UPS nutdev \"Main UPS\"",
+            "UPS pdu2 \"Spare PDU\"",
+            "END LIST UPS",
+            "BEGIN LIST UPS",
+            "UPS pdu1 \"Rack PDU\"",
+            "UPS nutdev \"Main UPS\"",
+            "UPS pdu2 \"Spare PDU\"",
+            "END LIST UPS",
+            "BEGIN LIST UPS",
+            "UPS pdu1 \"Rack PDU\"",
+            "UPS nutdev \"Main UPS\"",
+            "UPS pdu2 \"Spare PDU\"",
+            "END LIST UPS",
+        ]);
+
+        let names = |devices: Vec<(String, String)>| {
+            devices
+                .into_iter()
+                .map(|(name, _)| name)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            names(conn.list_ups_filtered("pdu").unwrap()),
+            vec!["pdu1", "pdu2"]
+        );
+        assert_eq!(
+            names(conn.list_ups_filtered("").unwrap()),
+            vec!["pdu1", "nutdev", "pdu2"]
+        );
+        assert!(conn.list_ups_filtered("ups").unwrap().is_empty());
+        assert_eq!(written(&output), "LIST UPS\nLIST UPS\nLIST UPS\n");
+    }
+
+    #[test]
+    fn test_common_metrics() {
+        let (mut conn, output) = mock_conn(&[
+            "VAR nutdev battery.charge \"100\"",
+            "VAR nutdev ups.load \"23.5\"",
+            "ERR VAR-NOT-SUPPORTED",
+            "VAR nutdev ups.status \"OL\"",
+        ]);
+
+        let metrics = conn.common_metrics("nutdev").unwrap();
+        assert_eq!(metrics.battery_charge, Some(100.0));
+        assert_eq!(metrics.ups_load, Some(23.5));
+        assert_eq!(metrics.input_voltage, None);
+        assert!(metrics.status.contains(&crate::UpsStatusFlag::OnLine));
+        assert_eq!(
+            written(&output),
+            "GET VAR nutdev battery.charge\nGET VAR nutdev ups.load\n\
+             GET VAR nutdev input.voltage\nGET VAR nutdev ups.status\n"
+        );
+    }
+
+    #[test]
+    fn test_get_var_detailed() {
+        let (mut conn, output) = mock_conn(&[
+            "VAR nutdev ups.load \"23\"",
+            "DESC nutdev ups.load \"Load on UPS (percent)\"",
+            "ERR VAR-NOT-SUPPORTED",
+            "ERR VAR-NOT-SUPPORTED",
+            "VAR nutdev ups.status \"OL\"",
+        ]);
+
+        let (var, desc) = conn.get_var_detailed("nutdev", "ups.load").unwrap();
+        assert_eq!((var.name(), var.value().as_str()), ("ups.load", "23"));
+        assert_eq!(desc, "Load on UPS (percent)");
+
+        // Both errors are consumed, so the next query is not out of step
+        assert!(matches!(
+            conn.get_var_detailed("nutdev", "input.voltage"),
+            Err(ClientError::Nut(NutError::VarNotSupported))
+        ));
+        assert_eq!(conn.get_var("nutdev", "ups.status").unwrap().value(), "OL");
+        assert_eq!(
+            written(&output),
+            "GET VAR nutdev ups.load\nGET DESC nutdev ups.load\n\
+             GET VAR nutdev input.voltage\nGET DESC nutdev input.voltage\n\
+             GET VAR nutdev ups.status\n"
+        );
+    }
+
+    #[test]
+    fn test_device_identity() {
+        let (mut conn, output) = mock_conn(&[
+            "VAR nutdev device.model \"Smart-UPS 1500\"",
+            "VAR nutdev device.mfr \"APC\"",
+            "ERR VAR-NOT-SUPPORTED",
+            "VAR nutdev device.type \"ups\"",
+            "ERR VAR-NOT-SUPPORTED",
+        ]);
+
+        let identity = conn.device_identity("nutdev").unwrap();
+        assert_eq!(identity.model.as_deref(), Some("Smart-UPS 1500"));
+        assert_eq!(identity.manufacturer.as_deref(), Some("APC"));
+        assert_eq!(identity.serial, None);
+        assert_eq!(identity.device_type.as_deref(), Some("ups"));
+        assert_eq!(identity.location, None);
+        assert_eq!(
+            written(&output),
+            "GET VAR nutdev device.model\nGET VAR nutdev device.mfr\n\
+             GET VAR nutdev device.serial\nGET VAR nutdev device.type\n\
+             GET VAR nutdev device.location\n"
+        );
+    }
+
+    #[test]
+    fn test_for_each_ups() {
+        let (mut conn, _) = mock_conn(&[
+            "BEGIN LIST UPS",
+            "UPS nutdev1 \"First UPS\"",
+            "UPS nutdev2 \"Second UPS\"",
+            "END LIST UPS",
+            "BEGIN LIST UPS",
+            "UPS nutdev1 \"First UPS\"",
+            "UPS nutdev2 \"Second UPS\"",
+            "END LIST UPS",
+            "1.2",
+        ]);
+
+        let mut devices = Vec::new();
+        conn.for_each_ups(|name, description| {
+            devices.push(format!("{}: {}", name, description));
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(devices, vec!["nutdev1: First UPS", "nutdev2: Second UPS"]);
+
+        // A failing callback stops receiving rows, but the list is fully read
+        let mut calls = 0;
+        let result = conn.for_each_ups(|_, _| {
+            calls += 1;
+            Err(ClientError::generic("stop"))
+        });
+        assert!(result.is_err());
+        assert_eq!(calls, 1);
+        assert_eq!(conn.get_network_version().unwrap(), "1.2");
+    }
+
+    #[test]
+    #[cfg(feature = "write")]
+    fn test_set_var_replies() {
+        let (mut conn, output) = mock_conn(&[
+            "OK",
+            "OK ups.id",
+            "OK TRACKING abc123",
+            "OK TRACKING def456",
+        ]);
+
+        assert_eq!(conn.set_var_tracked("nutdev", "ups.id", "1").unwrap(), None);
+        assert_eq!(conn.set_var_tracked("nutdev", "ups.id", "2").unwrap(), None);
+        assert_eq!(
+            conn.set_var_tracked("nutdev", "ups.id", "3").unwrap(),
+            Some("abc123".into())
+        );
+        conn.set_var("nutdev", "ups.id", "4").unwrap();
+        assert_eq!(
+            written(&output),
+            "SET VAR nutdev ups.id 1\nSET VAR nutdev ups.id 2\n\
+            SET VAR nutdev ups.id 3\nSET VAR nutdev ups.id 4\n"
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "write")]
+    fn test_fsd_with_confirmation() {
+        let (mut conn, output) = mock_conn(&["OK FSD-SET", "ERR ACCESS-DENIED"]);
+
+        conn.fsd_with_confirmation("nutdev", crate::FsdConfirm::yes_really())
+            .unwrap();
+        assert!(matches!(
+            conn.fsd_with_confirmation("nutdev", crate::FsdConfirm::yes_really()),
+            Err(ClientError::Nut(NutError::AccessDenied))
+        ));
+        assert_eq!(written(&output), "FSD nutdev\nFSD nutdev\n");
+    }
+
+    #[test]
+    #[cfg(feature = "write")]
+    fn test_set_vars() {
+        let (mut conn, output) = mock_conn(&["OK", "ERR READONLY", "OK"]);
+
+        let report = conn
+            .set_vars(
+                "nutdev",
+                &[
+                    ("input.transfer.low", "90"),
+                    ("ups.mfr", "ACME"),
+                    ("ups.id", "Rack 2"),
+                ],
+            )
+            .unwrap();
+        assert_eq!(report.applied, vec!["input.transfer.low", "ups.id"]);
+        assert_eq!(report.failed.len(), 1);
+        assert_eq!(report.failed[0].0, "ups.mfr");
+        assert!(matches!(
+            report.failed[0].1,
+            ClientError::Nut(NutError::ReadOnly)
+        ));
+        assert!(report.skipped.is_empty());
+        assert!(!report.is_complete());
+        assert_eq!(
+            written(&output),
+            "SET VAR nutdev input.transfer.low 90\nSET VAR nutdev ups.mfr ACME\n\
+             SET VAR nutdev ups.id \"Rack 2\"\n"
+        );
+
+        // The remaining variables are skipped once the connection is out of step
+        let (mut conn, _) = mock_conn(&["OK", "VAR nutdev b \"1\"", "OK"]);
+        let report = conn
+            .set_vars("nutdev", &[("a", "1"), ("b", "2"), ("c", "3")])
+            .unwrap();
+        assert_eq!(report.applied, vec!["a"]);
+        assert_eq!(report.failed[0].0, "b");
+        assert_eq!(report.skipped, vec!["c"]);
+    }
+
+    #[test]
+    fn test_list_vars_raw() {
+        let (mut conn, output) = mock_conn(&[
+            "BEGIN LIST VAR nutdev",
+            "VAR nutdev battery.charge \"100\"",
+            "VAR nutdev ups.status \"OL CHRG\"",
+            "END LIST VAR nutdev",
+        ]);
+
+        assert_eq!(
+            conn.list_vars_raw("nutdev").unwrap(),
+            vec![
+                ("battery.charge".to_string(), "100".to_string()),
+                ("ups.status".to_string(), "OL CHRG".to_string()),
+            ]
+        );
+        assert_eq!(written(&output), "LIST VAR nutdev\n");
+    }
+
+    #[test]
+    fn test_list_writable_names() {
+        let (mut conn, _) = mock_conn(&[
+            "BEGIN LIST RW nutdev",
+            "RW nutdev input.transfer.low \"90\"",
+            "RW nutdev ups.delay.shutdown \"20\"",
+            "END LIST RW nutdev",
+        ]);
+
+        assert_eq!(
+            conn.list_writable_names("nutdev").unwrap(),
+            vec!["input.transfer.low", "ups.delay.shutdown"]
+        );
+    }
+
+    #[test]
+    fn test_get_var_wrong_device() {
+        let (mut conn, _) = mock_conn(&["VAR otherups ups.load \"23\""]);
+
+        assert!(matches!(
+            conn.get_var("nutdev", "ups.load"),
+            Err(ClientError::Nut(NutError::UnexpectedResponse))
+        ));
+    }
+
+    #[test]
+    fn test_read_list_mismatched_end() {
+        let (mut conn, _) = mock_conn(&[
+            "BEGIN LIST VAR nutdev",
+            "VAR nutdev ups.load \"23\"",
+            "END LIST VAR otherups",
+        ]);
+
+        assert!(matches!(
+            conn.list_vars("nutdev"),
+            Err(ClientError::Nut(NutError::UnexpectedResponse))
+        ));
+    }
+
+    #[test]
+    fn test_deadline() {
+        // The server accepts the connection, but never responds
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let config = ConfigBuilder::new()
+            .with_host(listener.local_addr().unwrap().into())
+            .with_deadline(Instant::now() + std::time::Duration::from_millis(100))
+            .build();
+
+        assert!(matches!(
+            Connection::new(&config),
+            Err(ClientError::Nut(NutError::Timeout))
+        ));
+    }
+
+    #[test]
+    fn test_read_timeout() {
+        // The server answers the version probes, then stops responding
+        let (addr, server) = probe_server();
+        let config = ConfigBuilder::new()
+            .with_host(addr.into())
+            .with_read_timeout(std::time::Duration::from_millis(50))
+            .with_write_timeout(std::time::Duration::from_secs(1))
+            .build();
+
+        let mut conn = Connection::new(&config).unwrap();
+        let _stream = server.join().unwrap();
+        assert!(matches!(
+            conn.get_var("nutdev", "ups.status"),
+            Err(ClientError::Nut(NutError::Timeout))
+        ));
+    }
+
+    #[test]
+    fn test_close_with_timeout() {
+        // The server answers the version probes, then never answers the logout
+        let (addr, server) = probe_server();
+        let config = ConfigBuilder::new().with_host(addr.into()).build();
+
+        let conn = Connection::new(&config).unwrap();
+        let _stream = server.join().unwrap();
+        let start = Instant::now();
+        conn.close_with_timeout(std::time::Duration::from_millis(50))
+            .unwrap();
+        assert!(start.elapsed() < std::time::Duration::from_secs(2));
+    }
+
+    #[test]
+    fn test_read_interrupted() {
+        use std::io::{Error, ErrorKind};
+
+        let stream = FlakyStream {
+            reads: vec![
+                Ok(&b"1.2\n"[..]),
+                Ok(&b"Network UPS Tools upsd 2.8.0\n"[..]),
+                Ok(&b"VAR nutdev ups."[..]),
+                Err(Error::from(ErrorKind::Interrupted)),
+                Ok(&b"status \"OL\"\n"[..]),
+                Ok(&b"VAR nutdev "[..]),
+                Err(Error::from(ErrorKind::WouldBlock)),
+            ]
+            .into(),
+        };
+        let config = ConfigBuilder::new()
+            .with_read_timeout(std::time::Duration::from_secs(1))
+            .build();
+        let mut conn = Connection::from_stream(&config, stream).unwrap();
+
+        // Interruptions are retried, even in the middle of a line
+        assert_eq!(conn.get_var("nutdev", "ups.status").unwrap().value(), "OL");
+        // A read timing out on a socket with a timeout is reported as such
+        assert!(matches!(
+            conn.get_var("nutdev", "ups.status"),
+            Err(ClientError::Nut(NutError::Timeout))
+        ));
+    }
+
+    #[test]
+    fn test_parse_line_crlf() {
+        let mut reader = BufReader::new("OK\r\nVAR nutdev test.var \"a b\"\r\n".as_bytes());
+
+        let args = TcpConnection::parse_line(&mut reader, false).unwrap();
+        assert!(matches!(Response::from_args(args).unwrap(), Response::Ok));
+
+        let args = TcpConnection::parse_line(&mut reader, false).unwrap();
+        assert_eq!(args, vec!["VAR", "nutdev", "test.var", "a b"]);
     }
 }
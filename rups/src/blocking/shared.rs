@@ -0,0 +1,220 @@
+use std::collections::HashSet;
+use std::sync::{Mutex, MutexGuard};
+
+use crate::blocking::Connection;
+use crate::{
+    ClientError, Config, NutError, ServerVersion, UpsStatusFlag, Variable, VariableDefinition,
+    VariableRange,
+};
+
+/// A blocking NUT client connection that can be shared between threads.
+///
+/// A NUT connection is a single stream: the server answers requests one at a time, in the
+/// order they were sent. This wrapper guards the [`Connection`] with a [`Mutex`], so each
+/// request (and its response) is serialized, and exposes the read-only queries behind `&self`.
+/// It can be placed in an [`Arc`](std::sync::Arc) and used from several threads at once;
+/// concurrent callers will simply wait for each other.
+///
+/// For anything not exposed here, use [`SharedConnection::lock`] to borrow the underlying
+/// connection for the duration of a sequence of requests.
+pub struct SharedConnection {
+    inner: Mutex<Connection>,
+}
+
+macro_rules! delegate_shared {
+    (
+        $(
+            $(#[$attr:meta])+
+            fn $name:ident($($argname:ident: $argty:ty),*) -> $retty:ty;
+        )*
+    ) => {
+        impl SharedConnection {
+            $(
+                $(#[$attr])*
+                pub fn $name(&self$(, $argname: $argty)*) -> crate::Result<$retty> {
+                    self.lock()?.$name($($argname),*)
+                }
+            )*
+        }
+    };
+}
+
+impl SharedConnection {
+    /// Initializes a connection to a NUT server (upsd), ready to be shared.
+    pub fn new(config: &Config) -> crate::Result<Self> {
+        Ok(Self::from(Connection::new(config)?))
+    }
+
+    /// Locks the underlying connection, waiting for any other thread to release it.
+    ///
+    /// Returns an error if another thread panicked while holding the connection, since its
+    /// stream may have been left in the middle of a response.
+    pub fn lock(&self) -> crate::Result<MutexGuard<'_, Connection>> {
+        self.inner.lock().map_err(|_| {
+            ClientError::Nut(NutError::generic(
+                "Shared connection is unusable: a thread panicked while using it",
+            ))
+        })
+    }
+
+    /// Consumes the wrapper and returns the underlying connection.
+    pub fn into_inner(self) -> crate::Result<Connection> {
+        self.inner.into_inner().map_err(|_| {
+            ClientError::Nut(NutError::generic(
+                "Shared connection is unusable: a thread panicked while using it",
+            ))
+        })
+    }
+
+    /// Gracefully closes the connection.
+    pub fn close(self) -> crate::Result<()> {
+        self.into_inner()?.close()
+    }
+}
+
+impl From<Connection> for SharedConnection {
+    fn from(connection: Connection) -> Self {
+        Self {
+            inner: Mutex::new(connection),
+        }
+    }
+}
+
+delegate_shared! {
+    /// Queries a list of UPS devices.
+    fn list_ups() -> Vec<(String, String)>;
+
+    /// Queries the list of client IP addresses connected to the given device.
+    fn list_clients(ups_name: &str) -> Vec<String>;
+
+    /// Queries the clients connected to every UPS device, as `(ups_name, client_ip)` pairs.
+    fn list_all_clients() -> Vec<(String, String)>;
+
+    /// Queries the list of variables for a UPS device.
+    fn list_vars(ups_name: &str) -> Vec<Variable>;
+
+    /// Queries the list of mutable variables for a UPS device.
+    fn list_mutable_vars(ups_name: &str) -> Vec<Variable>;
+
+    /// Queries the names of the mutable variables for a UPS device.
+    fn list_writable_names(ups_name: &str) -> Vec<String>;
+
+    /// Queries the list of commands available for the given device.
+    fn list_commands(ups_name: &str) -> Vec<String>;
+
+    /// Queries the possible ranges of a UPS variable.
+    fn list_var_range(ups_name: &str, variable: &str) -> Vec<VariableRange>;
+
+    /// Queries the possible enum values of a UPS variable.
+    fn list_var_enum(ups_name: &str, variable: &str) -> Vec<String>;
+
+    /// Queries one variable for a UPS device.
+    fn get_var(ups_name: &str, variable: &str) -> Variable;
+
+    /// Queries the status flags of a UPS device (`ups.status`).
+    fn get_ups_status(ups_name: &str) -> HashSet<UpsStatusFlag>;
+
+    /// Queries the description of a UPS variable.
+    fn get_var_description(ups_name: &str, variable: &str) -> String;
+
+    /// Queries the type of a UPS variable.
+    fn get_var_type(ups_name: &str, variable: &str) -> VariableDefinition;
+
+    /// Queries the description of a UPS command.
+    fn get_command_description(ups_name: &str, variable: &str) -> String;
+
+    /// Queries the description of a UPS device.
+    fn get_ups_description(ups_name: &str) -> String;
+
+    /// Queries the number of logins to the specified UPS.
+    fn get_num_logins(ups_name: &str) -> i32;
+
+    /// Queries the network protocol version.
+    fn get_network_version() -> String;
+
+    /// Queries the server NUT version.
+    fn get_server_version() -> String;
+
+    /// Queries the server NUT version, parsed into its components.
+    fn get_server_version_parsed() -> ServerVersion;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+    use std::io::{Read, Write};
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::ConfigBuilder;
+
+    /// A stream that answers each `GET VAR` with the name of the variable as its value (and
+    /// the version probes), as soon as the request line is written.
+    #[derive(Default)]
+    struct EchoStream {
+        request: Vec<u8>,
+        replies: VecDeque<u8>,
+    }
+
+    impl Read for EchoStream {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.replies.read(buf)
+        }
+    }
+
+    impl Write for EchoStream {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            for &byte in buf {
+                if byte != b'\n' {
+                    self.request.push(byte);
+                    continue;
+                }
+                let request = String::from_utf8(std::mem::take(&mut self.request)).unwrap();
+                let reply = match request.strip_prefix("GET VAR ") {
+                    Some(args) => {
+                        let (ups_name, variable) = args.split_once(' ').unwrap();
+                        format!("VAR {} {} \"{}\"\n", ups_name, variable, variable)
+                    }
+                    None if request == "VER" => "Network UPS Tools upsd 2.8.0\n".to_string(),
+                    None => "1.2\n".to_string(),
+                };
+                self.replies.extend(reply.bytes());
+            }
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_shared_connection_threads() {
+        let conn =
+            Connection::from_stream(&ConfigBuilder::new().build(), EchoStream::default()).unwrap();
+        let shared = Arc::new(SharedConnection::from(conn));
+
+        let threads: Vec<_> = (0..4)
+            .map(|thread| {
+                let shared = shared.clone();
+                std::thread::spawn(move || {
+                    for i in 0..50 {
+                        let variable = format!("thread{}.var{}", thread, i);
+                        let var = shared.get_var("nutdev", &variable).unwrap();
+                        assert_eq!(var.name(), variable);
+                        assert_eq!(var.value(), variable);
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_shared_connection_is_send_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<SharedConnection>();
+    }
+}
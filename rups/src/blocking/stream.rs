@@ -1,23 +1,84 @@
 use std::io::{Read, Write};
 use std::net::TcpStream;
+use std::time::Duration;
+
+/// A stream that is both readable and writable, such as a custom transport.
+pub trait ReadWrite: Read + Write + Send {}
+
+impl<T: Read + Write + Send> ReadWrite for T {}
 
 /// A wrapper for various synchronous stream types.
 pub enum ConnectionStream {
     /// A plain TCP stream.
     Plain(TcpStream),
 
+    /// A stream provided by the user.
+    Custom(Box<dyn ReadWrite>),
+
     /// A stream wrapped with SSL using `rustls`.
     #[cfg(feature = "ssl")]
     Ssl(Box<rustls::StreamOwned<rustls::ClientSession, ConnectionStream>>),
 }
 
 impl ConnectionStream {
+    /// Sets the read and write timeouts of the underlying socket, if any.
+    pub fn set_timeouts(
+        &self,
+        read_timeout: Option<Duration>,
+        write_timeout: Option<Duration>,
+    ) -> std::io::Result<()> {
+        match self {
+            Self::Plain(stream) => {
+                stream.set_read_timeout(read_timeout)?;
+                stream.set_write_timeout(write_timeout)
+            }
+            Self::Custom(_) => Ok(()),
+            #[cfg(feature = "ssl")]
+            Self::Ssl(stream) => stream.sock.set_timeouts(read_timeout, write_timeout),
+        }
+    }
+
+    /// The plain TCP stream, if the stream is neither custom nor wrapped with SSL.
+    pub fn tcp_stream(&self) -> Option<&TcpStream> {
+        match self {
+            Self::Plain(stream) => Some(stream),
+            _ => None,
+        }
+    }
+
+    /// Whether the stream is wrapped with SSL.
+    pub fn is_ssl(&self) -> bool {
+        match self {
+            Self::Plain(_) | Self::Custom(_) => false,
+            #[cfg(feature = "ssl")]
+            Self::Ssl(_) => true,
+        }
+    }
+
+    /// The negotiated TLS parameters, if the stream is wrapped with SSL.
+    #[cfg(feature = "ssl")]
+    pub fn tls_info(&self) -> Option<crate::TlsInfo> {
+        match self {
+            Self::Plain(_) | Self::Custom(_) => None,
+            Self::Ssl(stream) => crate::TlsInfo::from_session(&stream.sess),
+        }
+    }
+
     /// Wraps the current stream with SSL using `rustls`.
     #[cfg(feature = "ssl")]
     pub fn upgrade_ssl(self, session: rustls::ClientSession) -> crate::Result<ConnectionStream> {
-        Ok(ConnectionStream::Ssl(Box::new(rustls::StreamOwned::new(
-            session, self,
-        ))))
+        use rustls::Session;
+
+        let mut stream = rustls::StreamOwned::new(session, self);
+        // Complete the handshake now, rather than on the first request, so that
+        // a failure is reported as such and nothing is ever sent unencrypted.
+        while stream.sess.is_handshaking() {
+            stream
+                .sess
+                .complete_io(&mut stream.sock)
+                .map_err(|e| crate::NutError::SslHandshakeFailed(e.to_string()))?;
+        }
+        Ok(ConnectionStream::Ssl(Box::new(stream)))
     }
 }
 
@@ -25,6 +86,7 @@ impl Read for ConnectionStream {
     fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
         match self {
             Self::Plain(stream) => stream.read(buf),
+            Self::Custom(stream) => stream.read(buf),
             #[cfg(feature = "ssl")]
             Self::Ssl(stream) => stream.read(buf),
         }
@@ -35,6 +97,7 @@ impl Write for ConnectionStream {
     fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
         match self {
             Self::Plain(stream) => stream.write(buf),
+            Self::Custom(stream) => stream.write(buf),
             #[cfg(feature = "ssl")]
             Self::Ssl(stream) => stream.write(buf),
         }
@@ -43,6 +106,7 @@ impl Write for ConnectionStream {
     fn flush(&mut self) -> std::io::Result<()> {
         match self {
             Self::Plain(stream) => stream.flush(),
+            Self::Custom(stream) => stream.flush(),
             #[cfg(feature = "ssl")]
             Self::Ssl(stream) => stream.flush(),
         }
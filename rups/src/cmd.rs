@@ -1,7 +1,25 @@
 use core::fmt;
+use std::collections::{HashMap, HashSet};
 use std::convert::TryFrom;
+use std::time::{Duration, Instant};
 
-use crate::{ClientError, NutError, Variable, VariableDefinition, VariableRange};
+use crate::proto::util::join_sentence;
+use crate::proto::{ClientSentences, ServerSentences};
+use crate::util::glob_matches;
+use crate::{
+    key, Capabilities, ClientError, CommandSchema, CommonMetrics, DeviceIdentity, DeviceReport,
+    DeviceSchema, DeviceSnapshot, EditableVar, NutError, Report, ServerVersion, UpsStatusFlag,
+    VarConstraints, Variable, VariableDefinition, VariableRange, VariableSchema,
+};
+
+#[cfg(feature = "write")]
+use crate::{FsdConfirm, SetReport};
+
+/// The number of requests sent at once when pipelining, before reading their responses.
+///
+/// The server only reads further requests while there is room to send its responses, so
+/// writing an unbounded number of requests before reading could deadlock.
+pub(crate) const PIPELINE_BATCH: usize = 32;
 
 #[derive(Debug, Clone)]
 pub enum Command<'a> {
@@ -18,9 +36,23 @@ pub enum Command<'a> {
     NetworkVersion,
     /// Queries the server version.
     Version,
+    /// Asserts primary-level access to a UPS device (NUT 2.8+).
+    Primary(&'a str),
+    /// Asserts primary-level access to a UPS device, using the name from before NUT 2.8.
+    Master(&'a str),
+    /// Logs in to a UPS device.
+    Login(&'a str),
+    /// Queries the commands supported by the server.
+    Help,
     #[cfg(feature = "write")]
-    /// Run a command. Allow for on additional optional param.
-    Run(&'a str, Option<&'a str>),
+    /// Run a command on a UPS device. Allow for on additional optional param.
+    Run(&'a str, &'a str, Option<&'a str>),
+    #[cfg(feature = "write")]
+    /// Sets the value of a variable on a UPS device.
+    Set(&'a str, &'a str, &'a str),
+    #[cfg(feature = "write")]
+    /// Sets the forced shutdown flag on a UPS device.
+    Fsd(&'a str),
     /// Gracefully shuts down the connection.
     Logout,
 }
@@ -36,8 +68,16 @@ impl<'a> Command<'a> {
             Self::StartTLS => "STARTTLS",
             Self::NetworkVersion => "NETVER",
             Self::Version => "VER",
+            Self::Primary(_) => "PRIMARY",
+            Self::Master(_) => "MASTER",
+            Self::Login(_) => "LOGIN",
+            Self::Help => "HELP",
+            #[cfg(feature = "write")]
+            Self::Run(_, _, _) => "INSTCMD",
+            #[cfg(feature = "write")]
+            Self::Set(_, _, _) => "SET",
             #[cfg(feature = "write")]
-            Self::Run(_, _) => "INSTCMD",
+            Self::Fsd(_) => "FSD",
             Self::Logout => "LOGOUT",
         }
     }
@@ -49,10 +89,17 @@ impl<'a> Command<'a> {
             Self::SetUsername(username) => vec![username],
             Self::SetPassword(password) => vec![password],
             Self::List(query) => query.to_vec(),
+            Self::Primary(ups_name) | Self::Master(ups_name) | Self::Login(ups_name) => {
+                vec![ups_name]
+            }
+            #[cfg(feature = "write")]
+            Self::Run(ups_name, cmd, param) => param
+                .map(|param| vec![*ups_name, cmd, param])
+                .unwrap_or_else(|| vec![ups_name, cmd]),
             #[cfg(feature = "write")]
-            Self::Run(cmd, param) => param
-                .map(|param| vec![*cmd, param])
-                .unwrap_or_else(|| vec![cmd]),
+            Self::Set(ups_name, variable, value) => vec!["VAR", ups_name, variable, value],
+            #[cfg(feature = "write")]
+            Self::Fsd(ups_name) => vec![ups_name],
             _ => Vec::new(),
         }
     }
@@ -62,22 +109,85 @@ impl<'a> fmt::Display for Command<'a> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let mut args = self.args();
         args.insert(0, self.name());
-        write!(f, "{}", shell_words::join(args))
+        write!(f, "{}", join_sentence(args))
+    }
+}
+
+/// An owned NUT command, for queries that are built at runtime.
+///
+/// Unlike the borrowed command used internally, this does not require the
+/// arguments to outlive the command.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct OwnedCommand {
+    name: String,
+    args: Vec<String>,
+}
+
+impl OwnedCommand {
+    /// Creates a command from its network identifier (e.g. `LIST`) and arguments.
+    pub fn new<N: Into<String>>(name: N, args: Vec<String>) -> Self {
+        OwnedCommand {
+            name: name.into(),
+            args,
+        }
+    }
+
+    /// Creates a `GET` query from its arguments.
+    pub fn get(query: Vec<String>) -> Self {
+        Self::new("GET", query)
+    }
+
+    /// Creates a `LIST` query from its arguments.
+    pub fn list(query: Vec<String>) -> Self {
+        Self::new("LIST", query)
+    }
+
+    /// The network identifier of the command.
+    pub fn name(&self) -> &str {
+        self.name.as_str()
+    }
+
+    /// The arguments of the command to serialize.
+    pub fn args(&self) -> &[String] {
+        self.args.as_slice()
+    }
+}
+
+impl<'a> From<Command<'a>> for OwnedCommand {
+    fn from(cmd: Command<'a>) -> Self {
+        OwnedCommand::new(
+            cmd.name(),
+            cmd.args().into_iter().map(String::from).collect(),
+        )
+    }
+}
+
+impl fmt::Display for OwnedCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut args = vec![self.name()];
+        args.extend(self.args.iter().map(String::as_str));
+        write!(f, "{}", join_sentence(args))
     }
 }
 
 #[derive(Debug, Clone)]
 pub enum Response {
     /// A successful response.
+    ///
+    /// Some servers echo details after `OK` (e.g. `OK FSD-SET`), which are ignored.
     Ok,
+    /// A successful response to a request tracked by the server (`OK TRACKING <id>`).
+    ///
+    /// Params: (tracking id)
+    OkTracking(String),
     /// Marks the beginning of a list response.
     BeginList(String),
     /// Marks the end of a list response.
     EndList(String),
     /// A variable (VAR) response.
     ///
-    /// Params: (var name, var value)
-    Var(String, String),
+    /// Params: (device name, var name, var value)
+    Var(String, String, String),
     /// A UPS (UPS) response.
     ///
     /// Params: (device name, device description)
@@ -100,16 +210,16 @@ pub enum Response {
     UpsDesc(String),
     /// A mutable variable (RW) response.
     ///
-    /// Params: (var name, var value)
-    Rw(String, String),
+    /// Params: (device name, var name, var value)
+    Rw(String, String, String),
     /// A variable description (DESC) response.
     ///
     /// Params: (variable description)
     Desc(String),
     /// A NUMLOGINS response.
     ///
-    /// Params: (number of logins)
-    NumLogins(i32),
+    /// Params: (device name, number of logins)
+    NumLogins(String, i32),
     /// A variable type (TYPE) response.
     ///
     /// Params: (variable name, variable types)
@@ -133,17 +243,18 @@ impl Response {
         }
         let cmd_name = args.remove(0);
         match cmd_name.as_str() {
-            "OK" => Ok(Self::Ok),
+            "OK" => match args.as_slice() {
+                [tracking, id] if tracking == "TRACKING" => Ok(Self::OkTracking(id.clone())),
+                _ => Ok(Self::Ok),
+            },
             "ERR" => {
                 if args.is_empty() {
                     Err(ClientError::generic("Unspecified server error"))
                 } else {
                     let err_type = args.remove(0);
-                    match err_type.as_str() {
-                        "ACCESS-DENIED" => Err(NutError::AccessDenied.into()),
-                        "UNKNOWN-UPS" => Err(NutError::UnknownUps.into()),
-                        "FEATURE-NOT-CONFIGURED" => Err(NutError::FeatureNotConfigured.into()),
-                        _ => Err(NutError::generic(format!(
+                    match NutError::from_code(&err_type) {
+                        Some(err) => Err(err.into()),
+                        None => Err(NutError::generic(format!(
                             "Server error: {} {}",
                             err_type,
                             args.join(" ")
@@ -163,7 +274,7 @@ impl Response {
                             begin_type
                         )))
                     } else {
-                        let args = shell_words::join(args);
+                        let args = join_sentence(args);
                         Ok(Response::BeginList(args))
                     }
                 }
@@ -179,13 +290,13 @@ impl Response {
                             begin_type
                         )))
                     } else {
-                        let args = shell_words::join(args);
+                        let args = join_sentence(args);
                         Ok(Response::EndList(args))
                     }
                 }
             }
             "VAR" => {
-                let _var_device = if args.is_empty() {
+                let var_device = if args.is_empty() {
                     Err(ClientError::generic(
                         "Unspecified VAR device name in response",
                     ))
@@ -202,10 +313,10 @@ impl Response {
                 } else {
                     Ok(args.remove(0))
                 }?;
-                Ok(Response::Var(var_name, var_value))
+                Ok(Response::Var(var_device, var_name, var_value))
             }
             "RW" => {
-                let _var_device = if args.is_empty() {
+                let var_device = if args.is_empty() {
                     Err(ClientError::generic(
                         "Unspecified RW device name in response",
                     ))
@@ -222,7 +333,7 @@ impl Response {
                 } else {
                     Ok(args.remove(0))
                 }?;
-                Ok(Response::Rw(var_name, var_value))
+                Ok(Response::Rw(var_device, var_name, var_value))
             }
             "UPS" => {
                 let name = if args.is_empty() {
@@ -327,7 +438,7 @@ impl Response {
                 Ok(Response::Desc(desc))
             }
             "NUMLOGINS" => {
-                let _device = if args.is_empty() {
+                let device = if args.is_empty() {
                     Err(ClientError::generic(
                         "Unspecified NUMLOGINS device in response",
                     ))
@@ -344,7 +455,7 @@ impl Response {
                 let num = num
                     .parse::<i32>()
                     .map_err(|_| ClientError::generic("Invalid NUMLOGINS number in response"))?;
-                Ok(Response::NumLogins(num))
+                Ok(Response::NumLogins(device, num))
             }
             "TYPE" => {
                 let _device = if args.is_empty() {
@@ -407,13 +518,23 @@ impl Response {
 
     pub(crate) fn expect_ok(&self) -> crate::Result<&Response> {
         match self {
-            Self::Ok => Ok(self),
+            Self::Ok | Self::OkTracking(_) => Ok(self),
+            _ => Err(NutError::UnexpectedResponse.into()),
+        }
+    }
+
+    /// Expects a successful response, returning the tracking id if the server sent one.
+    #[cfg_attr(not(feature = "write"), allow(dead_code))]
+    pub(crate) fn expect_ok_tracking(self) -> crate::Result<Option<String>> {
+        match self {
+            Self::Ok => Ok(None),
+            Self::OkTracking(id) => Ok(Some(id)),
             _ => Err(NutError::UnexpectedResponse.into()),
         }
     }
 
     pub(crate) fn expect_begin_list(self, expected_args: &[&str]) -> crate::Result<Response> {
-        let expected_args = shell_words::join(expected_args);
+        let expected_args = join_sentence(expected_args);
         if let Self::BeginList(args) = &self {
             if &expected_args == args {
                 Ok(self)
@@ -425,19 +546,48 @@ impl Response {
         }
     }
 
-    pub(crate) fn expect_var(&self) -> crate::Result<Variable> {
-        if let Self::Var(name, value) = &self {
-            Ok(Variable::parse(name, value.to_owned()))
+    pub(crate) fn expect_end_list(self, expected_args: &[&str]) -> crate::Result<Response> {
+        let expected_args = join_sentence(expected_args);
+        if let Self::EndList(args) = &self {
+            if &expected_args == args {
+                Ok(self)
+            } else {
+                Err(NutError::UnexpectedResponse.into())
+            }
         } else {
             Err(NutError::UnexpectedResponse.into())
         }
     }
 
-    pub(crate) fn expect_rw(&self) -> crate::Result<Variable> {
-        if let Self::Rw(name, value) = &self {
-            Ok(Variable::parse(name, value.to_owned()))
-        } else {
-            Err(NutError::UnexpectedResponse.into())
+    pub(crate) fn expect_var(&self, ups_name: &str) -> crate::Result<Variable> {
+        match &self {
+            Self::Var(device, name, value) if device == ups_name => {
+                Ok(Variable::parse(name, value.to_owned()))
+            }
+            _ => Err(NutError::UnexpectedResponse.into()),
+        }
+    }
+
+    pub(crate) fn expect_raw_var(self, ups_name: &str) -> crate::Result<(String, String)> {
+        match self {
+            Self::Var(device, name, value) if device == ups_name => Ok((name, value)),
+            _ => Err(NutError::UnexpectedResponse.into()),
+        }
+    }
+
+    pub(crate) fn expect_rw(&self, ups_name: &str) -> crate::Result<Variable> {
+        match &self {
+            Self::Rw(device, name, value) if device == ups_name => {
+                Ok(Variable::parse(name, value.to_owned()))
+            }
+            _ => Err(NutError::UnexpectedResponse.into()),
+        }
+    }
+
+    pub(crate) fn expect_rw_name(self, ups_name: &str) -> crate::Result<String> {
+        match self {
+            Self::Rw(device, name, _) if device == ups_name => Ok(name),
+            _ => Err(NutError::UnexpectedResponse.into()),
         }
     }
 
@@ -490,13 +640,21 @@ impl Response {
     }
 
     pub(crate) fn expect_numlogins(&self) -> crate::Result<i32> {
-        if let Self::NumLogins(num) = &self {
+        if let Self::NumLogins(_, num) = &self {
             Ok(*num)
         } else {
             Err(NutError::UnexpectedResponse.into())
         }
     }
 
+    pub(crate) fn expect_numlogins_named(self) -> crate::Result<(String, i32)> {
+        if let Self::NumLogins(device, num) = self {
+            Ok((device, num))
+        } else {
+            Err(NutError::UnexpectedResponse.into())
+        }
+    }
+
     pub(crate) fn expect_type(&self) -> crate::Result<VariableDefinition> {
         if let Self::Type(name, types) = &self {
             VariableDefinition::try_from((
@@ -723,14 +881,6 @@ macro_rules! implement_action_commands {
 }
 
 implement_list_commands! {
-    /// Queries a list of UPS devices.
-    pub fn list_ups() -> Vec<(String, String)> {
-        (
-            { &["UPS"] },
-            { |row: Response| row.expect_ups() },
-        )
-    }
-
     /// Queries the list of client IP addresses connected to the given device.
     pub fn list_clients(ups_name: &str) -> Vec<String> {
         (
@@ -739,11 +889,21 @@ implement_list_commands! {
         )
     }
 
-    /// Queries the list of variables for a UPS device.
-    pub fn list_vars(ups_name: &str) -> Vec<Variable> {
+    /// Queries the list of variables for a UPS device, without logging in on `ACCESS-DENIED`.
+    fn query_list_vars(ups_name: &str) -> Vec<Variable> {
+        (
+            { &["VAR", ups_name] },
+            { |row: Response| row.expect_var(ups_name) },
+        )
+    }
+
+    /// Queries the list of variables for a UPS device, as raw `(name, value)` pairs.
+    ///
+    /// Unlike `list_vars`, the values are not parsed into [`Variable`]s.
+    pub fn list_vars_raw(ups_name: &str) -> Vec<(String, String)> {
         (
             { &["VAR", ups_name] },
-            { |row: Response| row.expect_var() },
+            { |row: Response| row.expect_raw_var(ups_name) },
         )
     }
 
@@ -751,7 +911,15 @@ implement_list_commands! {
     pub fn list_mutable_vars(ups_name: &str) -> Vec<Variable> {
         (
             { &["RW", ups_name] },
-            { |row: Response| row.expect_rw() },
+            { |row: Response| row.expect_rw(ups_name) },
+        )
+    }
+
+    /// Queries the names of the mutable variables for a UPS device.
+    pub fn list_writable_names(ups_name: &str) -> Vec<String> {
+        (
+            { &["RW", ups_name] },
+            { |row: Response| row.expect_rw_name(ups_name) },
         )
     }
 
@@ -781,11 +949,19 @@ implement_list_commands! {
 }
 
 implement_get_commands! {
-    /// Queries one variable for a UPS device.
-    pub fn get_var(ups_name: &str, variable: &str) -> Variable {
+    /// Queries one variable for a UPS device, without logging in on `ACCESS-DENIED`.
+    fn query_var(ups_name: &str, variable: &str) -> Variable {
         (
             { &["VAR", ups_name, variable] },
-            { |row: Response| row.expect_var() },
+            { |row: Response| row.expect_var(ups_name) },
+        )
+    }
+
+    /// Queries the status flags of a UPS device (`ups.status`).
+    pub fn get_ups_status(ups_name: &str) -> HashSet<UpsStatusFlag> {
+        (
+            { &["VAR", ups_name, key::UPS_STATUS] },
+            { |row: Response| row.expect_var(ups_name).map(|var| UpsStatusFlag::parse_status(&var.value())) },
         )
     }
 
@@ -828,6 +1004,15 @@ implement_get_commands! {
             { |row: Response| row.expect_numlogins() },
         )
     }
+
+    /// Queries the number of logins to the specified UPS, along with the device name
+    /// echoed by the server (e.g. to match responses to pipelined requests).
+    pub fn get_num_logins_named(ups_name: &str) -> (String, i32) {
+        (
+            { &["NUMLOGINS", ups_name] },
+            { |row: Response| row.expect_numlogins_named() },
+        )
+    }
 }
 
 implement_simple_commands! {
@@ -846,49 +1031,1528 @@ implement_simple_commands! {
             { Ok },
         )
     }
+
+    /// Queries the commands supported by the server (`HELP`).
+    pub fn help() -> Vec<String> {
+        (
+            { Command::Help },
+            { |raw: String| Ok(Capabilities::parse_help(&raw)) },
+        )
+    }
+
+    /// Queries the server NUT version, parsed into its components.
+    pub fn get_server_version_parsed() -> ServerVersion {
+        (
+            { Command::Version },
+            { |raw: String| Ok(ServerVersion::parse(raw)) },
+        )
+    }
 }
 
 implement_action_commands! {
     /// Sends the login username.
-    pub(crate) fn set_username(username: &str) {
+    ///
+    /// This is done automatically when connecting, unless auto-login is disabled
+    /// (see [`ConfigBuilder::with_auto_login`](crate::ConfigBuilder::with_auto_login)).
+    pub fn set_username(username: &str) {
         Command::SetUsername(username)
     }
 
     /// Sends the login password.
-    pub(crate) fn set_password(password: &str) {
+    ///
+    /// This is done automatically when connecting, unless auto-login is disabled
+    /// (see [`ConfigBuilder::with_auto_login`](crate::ConfigBuilder::with_auto_login)).
+    pub fn set_password(password: &str) {
         Command::SetPassword(password)
     }
 
+    /// Logs in to a UPS device (`LOGIN`), which requires the username and password to be set.
+    ///
+    /// upsd then counts the client as using the UPS for power (see `get_num_logins`),
+    /// which is how `upsmon` registers. A connection can only log in once.
+    pub fn login_ups(ups_name: &str) {
+        Command::Login(ups_name)
+    }
+
     /// Gracefully shuts down the connection.
     pub(crate) fn logout() {
         Command::Logout
     }
 }
 
-#[cfg(feature = "write")]
 impl crate::blocking::Connection {
-    /// Runs a command on the UPS.
-    pub fn run_command(&mut self, cmd: &str, param: Option<&str>) -> crate::Result<()> {
+    /// Queries a list of UPS devices.
+    pub fn list_ups(&mut self) -> crate::Result<Vec<(String, String)>> {
+        match self {
+            Self::Tcp(conn) => {
+                conn.write_sentence(&ServerSentences::QueryListUps {})?;
+                let sentences = conn.read_sentences_until(|sentence| {
+                    matches!(sentence, ClientSentences::EndListUps {})
+                })?;
+                let mut sentences = sentences.into_iter();
+                if sentences.next() != Some(ClientSentences::BeginListUps {}) {
+                    return Err(NutError::UnexpectedResponse.into());
+                }
+                sentences
+                    .filter_map(|sentence| match sentence {
+                        ClientSentences::RespondUps {
+                            ups_name,
+                            description,
+                        } => Some(Ok((ups_name, description))),
+                        ClientSentences::EndListUps {} => None,
+                        _ => Some(Err(NutError::UnexpectedResponse.into())),
+                    })
+                    .collect()
+            }
+        }
+    }
+
+    /// Queries a list of UPS devices, keeping only those whose name starts with `prefix`.
+    ///
+    /// The NUT protocol has no server-side filter, so this performs a full `LIST UPS`.
+    pub fn list_ups_filtered(&mut self, prefix: &str) -> crate::Result<Vec<(String, String)>> {
+        let mut devices = self.list_ups()?;
+        devices.retain(|(name, _)| name.starts_with(prefix));
+        Ok(devices)
+    }
+
+    /// Queries the variables of a UPS device, keeping only those whose name matches a glob
+    /// `pattern`, where `*` matches any characters (e.g. `battery.*`).
+    ///
+    /// The NUT protocol has no server-side filter, so this performs a full `LIST VAR`.
+    pub fn list_vars_matching(
+        &mut self,
+        ups_name: &str,
+        pattern: &str,
+    ) -> crate::Result<Vec<Variable>> {
+        let mut vars = self.list_vars(ups_name)?;
+        vars.retain(|var| glob_matches(pattern, var.name()));
+        Ok(vars)
+    }
+
+    /// Queries the clients connected to every UPS device, as `(ups_name, client_ip)` pairs.
+    pub fn list_all_clients(&mut self) -> crate::Result<Vec<(String, String)>> {
+        let mut clients = Vec::new();
+        for (ups_name, _) in self.list_ups()? {
+            for client_ip in self.list_clients(&ups_name)? {
+                clients.push((ups_name.clone(), client_ip));
+            }
+        }
+        Ok(clients)
+    }
+
+    /// Queries the mutable variables of a UPS device, along with their type definition
+    /// and allowed values (enum or range, as applicable).
+    pub fn list_editable(&mut self, ups_name: &str) -> crate::Result<Vec<EditableVar>> {
+        let vars = self.list_mutable_vars(ups_name)?;
+        match self {
+            Self::Tcp(conn) => {
+                let queries: Vec<String> = vars
+                    .iter()
+                    .map(|var| Command::Get(&["TYPE", ups_name, var.name()]).to_string())
+                    .collect();
+                let definitions = conn
+                    .pipeline_responses(&queries)?
+                    .into_iter()
+                    .map(|row| row.and_then(|row| row.expect_type()))
+                    .collect::<crate::Result<Vec<VariableDefinition>>>()?;
+
+                // The enum values and ranges are listed in the order of the variables
+                let mut queries: Vec<[&str; 3]> = Vec::new();
+                for (var, definition) in vars.iter().zip(&definitions) {
+                    if definition.is_enum() {
+                        queries.push(["ENUM", ups_name, var.name()]);
+                    }
+                    if definition.is_range() {
+                        queries.push(["RANGE", ups_name, var.name()]);
+                    }
+                }
+                let mut lists = conn.pipeline_lists(&queries)?.into_iter();
+                let mut next_list = || {
+                    lists
+                        .next()
+                        .unwrap_or_else(|| Err(NutError::UnexpectedResponse.into()))
+                };
+
+                let mut editable = Vec::with_capacity(vars.len());
+                for (var, definition) in vars.into_iter().zip(definitions) {
+                    let enum_values = if definition.is_enum() {
+                        next_list()?
+                            .iter()
+                            .map(Response::expect_enum)
+                            .collect::<crate::Result<Vec<String>>>()?
+                    } else {
+                        Vec::new()
+                    };
+                    let ranges = if definition.is_range() {
+                        next_list()?
+                            .iter()
+                            .map(Response::expect_range)
+                            .collect::<crate::Result<Vec<VariableRange>>>()?
+                    } else {
+                        Vec::new()
+                    };
+                    editable.push(EditableVar {
+                        name: var.name().to_owned(),
+                        value: var.value(),
+                        definition,
+                        enum_values,
+                        ranges,
+                    });
+                }
+                Ok(editable)
+            }
+        }
+    }
+
+    /// Queries the constraints on the values of a variable: its type, then its allowed
+    /// ranges and values, only if its type has them.
+    ///
+    /// This applies to read-only variables too, since some drivers give them a range
+    /// (e.g. diagnostic bounds).
+    pub fn var_constraints(
+        &mut self,
+        ups_name: &str,
+        variable: &str,
+    ) -> crate::Result<VarConstraints> {
+        let definition = self.get_var_type(ups_name, variable)?;
+        let ranges = if definition.is_range() {
+            self.list_var_range(ups_name, variable)?
+        } else {
+            Vec::new()
+        };
+        let enums = if definition.is_enum() {
+            self.list_var_enum(ups_name, variable)?
+        } else {
+            Vec::new()
+        };
+        Ok(VarConstraints {
+            definition,
+            ranges,
+            enums,
+        })
+    }
+
+    /// Queries one variable for a UPS device, returning `None` if the device doesn't
+    /// support it.
+    ///
+    /// Other errors (such as I/O, authentication or an unknown device) are still returned.
+    pub fn try_get_var(
+        &mut self,
+        ups_name: &str,
+        variable: &str,
+    ) -> crate::Result<Option<Variable>> {
+        match self.get_var(ups_name, variable) {
+            Ok(var) => Ok(Some(var)),
+            Err(ClientError::Nut(NutError::VarNotSupported)) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Queries the allowed values of every enumerated variable of a UPS device.
+    ///
+    /// The types (and then the values) of all variables are requested in pipelined batches,
+    /// rather than waiting for each response in turn.
+    pub fn list_all_enums(
+        &mut self,
+        ups_name: &str,
+    ) -> crate::Result<HashMap<String, Vec<String>>> {
+        let names: Vec<String> = self
+            .list_vars(ups_name)?
+            .iter()
+            .map(|var| var.name().to_owned())
+            .collect();
+
+        match self {
+            Self::Tcp(conn) => {
+                let queries: Vec<String> = names
+                    .iter()
+                    .map(|name| Command::Get(&["TYPE", ups_name, name]).to_string())
+                    .collect();
+                let definitions = conn.pipeline_responses(&queries)?;
+                let mut enum_names = Vec::new();
+                for (name, definition) in names.into_iter().zip(definitions) {
+                    if definition.and_then(|row| row.expect_type())?.is_enum() {
+                        enum_names.push(name);
+                    }
+                }
+
+                let queries: Vec<[&str; 3]> = enum_names
+                    .iter()
+                    .map(|name| ["ENUM", ups_name, name])
+                    .collect();
+                let lists = conn.pipeline_lists(&queries)?;
+                let mut enums = HashMap::new();
+                for (name, list) in enum_names.into_iter().zip(lists) {
+                    let values = list?
+                        .iter()
+                        .map(Response::expect_enum)
+                        .collect::<crate::Result<Vec<String>>>()?;
+                    enums.insert(name, values);
+                }
+                Ok(enums)
+            }
+        }
+    }
+
+    /// Queries one variable for a UPS device along with its description.
+    ///
+    /// Both queries are sent at once (pipelined), saving a round trip over calling
+    /// `get_var` and `get_var_description`.
+    pub fn get_var_detailed(
+        &mut self,
+        ups_name: &str,
+        variable: &str,
+    ) -> crate::Result<(Variable, String)> {
+        match self {
+            Self::Tcp(conn) => {
+                conn.write_lines(&[
+                    Command::Get(&["VAR", ups_name, variable]).to_string(),
+                    Command::Get(&["DESC", ups_name, variable]).to_string(),
+                ])?;
+                let [var, desc] = <[_; 2]>::try_from(conn.read_responses(2)?)
+                    .map_err(|_| NutError::UnexpectedResponse)?;
+                let var = var?.expect_var(ups_name)?;
+                let desc = desc?.expect_desc()?;
+                Ok((var, desc))
+            }
+        }
+    }
+
+    /// Guesses whether the primary `upsmon` (formerly "master") of a UPS device is logged in.
+    ///
+    /// NUT doesn't tell the role of logged-in clients, so this is a heuristic: the primary runs
+    /// on the machine the UPS is attached to, which is usually the one running upsd. It is
+    /// considered connected if any client (see `list_clients`) is logged in from a loopback
+    /// address. This is wrong if upsd runs on a different machine than the primary, or if
+    /// another local client (e.g. a secondary `upsmon`) is logged in. Use `get_num_logins` and
+    /// `list_clients` directly to apply other rules.
+    pub fn is_primary_connected(&mut self, ups_name: &str) -> crate::Result<bool> {
+        if self.get_num_logins(ups_name)? == 0 {
+            return Ok(false);
+        }
+        let clients = self.list_clients(ups_name)?;
+        Ok(clients
+            .iter()
+            .any(|addr| crate::util::is_loopback_client(addr)))
+    }
+
+    /// Queries one variable for a UPS device.
+    ///
+    /// If enabled (see
+    /// [`ConfigBuilder::with_auto_login_on_access_denied`](crate::ConfigBuilder::with_auto_login_on_access_denied)),
+    /// logs in to the device and retries once when access is denied. If logging in fails
+    /// (e.g. `ALREADY-LOGGED-IN` to another device), its error is returned instead.
+    pub fn get_var(&mut self, ups_name: &str, variable: &str) -> crate::Result<Variable> {
+        let result = self.query_var(ups_name, variable);
+        if self.should_login_after(&result) {
+            self.login_ups(ups_name)?;
+            return self.query_var(ups_name, variable);
+        }
+        result
+    }
+
+    /// Queries the list of variables for a UPS device.
+    ///
+    /// If enabled (see
+    /// [`ConfigBuilder::with_auto_login_on_access_denied`](crate::ConfigBuilder::with_auto_login_on_access_denied)),
+    /// logs in to the device and retries once when access is denied. If logging in fails
+    /// (e.g. `ALREADY-LOGGED-IN` to another device), its error is returned instead.
+    pub fn list_vars(&mut self, ups_name: &str) -> crate::Result<Vec<Variable>> {
+        let result = self.query_list_vars(ups_name);
+        if self.should_login_after(&result) {
+            self.login_ups(ups_name)?;
+            return self.query_list_vars(ups_name);
+        }
+        result
+    }
+
+    /// Whether the result is `ACCESS-DENIED`, and logging in to retry is enabled.
+    fn should_login_after<T>(&self, result: &crate::Result<T>) -> bool {
+        let config = match self {
+            Self::Tcp(conn) => conn.config(),
+        };
+        config.login_on_access_denied
+            && config
+                .auth
+                .as_ref()
+                .is_some_and(|auth| auth.password.is_some())
+            && matches!(result, Err(ClientError::Nut(NutError::AccessDenied)))
+    }
+
+    /// Queries the active alarms of a UPS device (`ups.alarm`), which are signaled by the
+    /// [`UpsStatusFlag::Alarm`] status flag.
+    ///
+    /// Returns an empty list if there is no alarm.
+    pub fn get_alarms(&mut self, ups_name: &str) -> crate::Result<Vec<String>> {
+        Ok(self
+            .try_get_var(ups_name, key::UPS_ALARM)?
+            .map(|var| crate::var::parse_alarms(&var.value()))
+            .unwrap_or_default())
+    }
+
+    /// Queries the protocol features supported by the server, derived from `HELP`, the
+    /// server version and the network version.
+    ///
+    /// The result is cached on the connection, so the server is only queried once.
+    pub fn capabilities(&mut self) -> crate::Result<Capabilities> {
+        match self {
+            Self::Tcp(conn) => {
+                if let Some(capabilities) = &conn.capabilities {
+                    return Ok(capabilities.clone());
+                }
+            }
+        }
+        let commands = self.help()?;
+        let version = self.known_server_version()?;
+        let capabilities = Capabilities::new(commands, &version, self.network_version());
+        match self {
+            Self::Tcp(conn) => conn.capabilities = Some(capabilities.clone()),
+        }
+        Ok(capabilities)
+    }
+
+    /// The server version probed when connecting, or queried (and kept) if it wasn't.
+    fn known_server_version(&mut self) -> crate::Result<ServerVersion> {
+        match self {
+            Self::Tcp(conn) => {
+                if let Some(version) = &conn.server_version {
+                    return Ok(version.clone());
+                }
+            }
+        }
+        let version = self.get_server_version_parsed()?;
+        match self {
+            Self::Tcp(conn) => conn.server_version = Some(version.clone()),
+        }
+        Ok(version)
+    }
+
+    /// Measures the round-trip time of a minimal request (`NETVER`) to the server.
+    pub fn ping_latency(&mut self) -> crate::Result<Duration> {
+        let start = Instant::now();
+        self.get_network_version()?;
+        Ok(start.elapsed())
+    }
+
+    /// Queries the [`CommonMetrics`] of a UPS device: battery charge, load, input voltage
+    /// and status.
+    ///
+    /// The variables are requested at once (pipelined). Variables that the device does not
+    /// support are left out of the metrics, rather than failing the whole query.
+    pub fn common_metrics(&mut self, ups_name: &str) -> crate::Result<CommonMetrics> {
+        let vars = self.get_supported_vars(ups_name, &CommonMetrics::KEYS)?;
+        Ok(vars.into_iter().collect())
+    }
+
+    /// Queries the [`DeviceIdentity`] of a UPS device: model, manufacturer, serial number,
+    /// type and location.
+    ///
+    /// The variables are requested at once (pipelined). Variables that the device does not
+    /// support are left as `None`, rather than failing the whole query.
+    pub fn device_identity(&mut self, ups_name: &str) -> crate::Result<DeviceIdentity> {
+        let vars = self.get_supported_vars(ups_name, &DeviceIdentity::KEYS)?;
+        Ok(vars.into_iter().collect())
+    }
+
+    /// Queries several variables at once (pipelined), leaving out those that the device
+    /// does not support.
+    fn get_supported_vars(
+        &mut self,
+        ups_name: &str,
+        names: &[&str],
+    ) -> crate::Result<Vec<Variable>> {
+        match self {
+            Self::Tcp(conn) => {
+                let queries: Vec<String> = names
+                    .iter()
+                    .map(|name| Command::Get(&["VAR", ups_name, name]).to_string())
+                    .collect();
+                let responses = conn.pipeline_responses(&queries)?;
+                let mut supported = Vec::with_capacity(responses.len());
+                for response in responses {
+                    match response.and_then(|row| row.expect_var(ups_name)) {
+                        Ok(var) => supported.push(var),
+                        Err(ClientError::Nut(NutError::VarNotSupported)) => {}
+                        Err(e) => return Err(e),
+                    }
+                }
+                Ok(supported)
+            }
+        }
+    }
+
+    /// Asserts primary-level (formerly "master") access to a UPS device, which the logged-in
+    /// user must have been granted (`upsmon primary` in `upsd.users`).
+    ///
+    /// This sends `PRIMARY` to servers from NUT 2.8 onwards, and `MASTER` to older (or
+    /// unrecognized) servers.
+    #[doc(alias = "master")]
+    pub fn primary(&mut self, ups_name: &str) -> crate::Result<()> {
+        let version = self.known_server_version()?;
         match self {
             Self::Tcp(conn) => {
-                conn.write_cmd(Command::Run(cmd, param))?;
+                if version.is_at_least((2, 8, 0)) {
+                    conn.write_cmd(Command::Primary(ups_name))?;
+                } else {
+                    conn.write_cmd(Command::Master(ups_name))?;
+                }
                 conn.read_response()?.expect_ok()?;
                 Ok(())
             }
         }
     }
-}
 
-#[cfg(all(feature = "write", feature = "async"))]
-impl crate::tokio::Connection {
-    /// Runs a command on the UPS.
-    pub async fn run_command(&mut self, cmd: &str, param: Option<&str>) -> crate::Result<()> {
+    /// Queries a snapshot of all the variables of a UPS device, which can be looked up by name.
+    pub fn snapshot(&mut self, ups_name: &str) -> crate::Result<DeviceSnapshot> {
+        Ok(self.list_vars(ups_name)?.into_iter().collect())
+    }
+
+    /// Queries the list of UPS devices, calling `f` with the name and description of each
+    /// device as soon as it is read, instead of collecting them.
+    ///
+    /// If `f` fails, it is not called for the remaining devices, and its error is returned.
+    pub fn for_each_ups<F>(&mut self, mut f: F) -> crate::Result<()>
+    where
+        F: FnMut(&str, &str) -> crate::Result<()>,
+    {
         match self {
             Self::Tcp(conn) => {
-                conn.write_cmd(Command::Run(cmd, param)).await?;
-                conn.read_response().await?.expect_ok()?;
-                Ok(())
+                let query = &["UPS"];
+                conn.write_cmd(Command::List(query))?;
+                conn.read_list_with(query, |row| {
+                    let (name, description) = row.expect_ups()?;
+                    f(&name, &description)
+                })
             }
         }
     }
+
+    /// Queries the complete schema of a UPS device: every variable (with its type,
+    /// description and allowed values) and every instant command (with its description).
+    ///
+    /// This performs several requests per variable and command.
+    pub fn schema(&mut self, ups_name: &str) -> crate::Result<DeviceSchema> {
+        let mut variables = Vec::new();
+        for var in self.list_vars(ups_name)? {
+            let description = self.get_var_description(ups_name, var.name())?;
+            let definition = self.get_var_type(ups_name, var.name())?;
+            let enum_values = if definition.is_enum() {
+                self.list_var_enum(ups_name, var.name())?
+            } else {
+                Vec::new()
+            };
+            let ranges = if definition.is_range() {
+                self.list_var_range(ups_name, var.name())?
+            } else {
+                Vec::new()
+            };
+            variables.push(VariableSchema {
+                name: var.name().to_owned(),
+                value: var.value(),
+                description,
+                types: definition.type_names(),
+                enum_values,
+                ranges,
+            });
+        }
+
+        let mut commands = Vec::new();
+        for name in self.list_commands(ups_name)? {
+            let description = self.get_command_description(ups_name, &name)?;
+            commands.push(CommandSchema { name, description });
+        }
+
+        Ok(DeviceSchema {
+            ups_name: ups_name.to_owned(),
+            variables,
+            commands,
+        })
+    }
+
+    /// Queries the state of the whole server: its versions, and the description, number of
+    /// logins and complete schema (see [`Connection::schema`](crate::blocking::Connection::schema)) of every UPS device.
+    ///
+    /// This performs many requests (several per variable), so it is best suited for
+    /// inventories rather than frequent polling.
+    pub fn full_report(&mut self) -> crate::Result<Report> {
+        let server_version = self.get_server_version()?;
+        let network_version = self.get_network_version()?;
+        let mut devices = Vec::new();
+        for (ups_name, description) in self.list_ups()? {
+            let num_logins = self.get_num_logins(&ups_name)?;
+            let schema = self.schema(&ups_name)?;
+            devices.push(DeviceReport {
+                description,
+                num_logins,
+                schema,
+            });
+        }
+        Ok(Report {
+            server_version,
+            network_version,
+            devices,
+        })
+    }
+
+    /// Sends a raw command line to the server, and returns the words of each response line.
+    ///
+    /// This is an escape hatch for protocol features that aren't covered by the typed API.
+    /// The line is sent as-is (a line break is appended). A single response line is read,
+    /// unless the server begins a list (`BEGIN LIST ...`), in which case all lines are read
+    /// until the matching `END`. An `ERR` response is returned as the corresponding error.
+    ///
+    /// Use with care: sending a command that produces an unexpected number of response
+    /// lines will leave the connection in an inconsistent state.
+    pub fn raw(&mut self, line: &str) -> crate::Result<Vec<Vec<String>>> {
+        match self {
+            Self::Tcp(conn) => {
+                conn.write_line(line)?;
+                conn.read_raw_response()
+            }
+        }
+    }
+
+    /// Sends a command built at runtime, and returns the words of each response line.
+    ///
+    /// The response is read as with [`raw`](Self::raw).
+    pub fn send_command(&mut self, cmd: &OwnedCommand) -> crate::Result<Vec<Vec<String>>> {
+        self.raw(&cmd.to_string())
+    }
+}
+
+#[cfg(feature = "async")]
+impl crate::tokio::Connection {
+    /// Queries a list of UPS devices.
+    pub async fn list_ups(&mut self) -> crate::Result<Vec<(String, String)>> {
+        match self {
+            Self::Tcp(conn) => {
+                conn.write_sentence(&ServerSentences::QueryListUps {})
+                    .await?;
+                let sentences = conn
+                    .read_sentences_until(|sentence| {
+                        matches!(sentence, ClientSentences::EndListUps {})
+                    })
+                    .await?;
+                let mut sentences = sentences.into_iter();
+                if sentences.next() != Some(ClientSentences::BeginListUps {}) {
+                    return Err(NutError::UnexpectedResponse.into());
+                }
+                sentences
+                    .filter_map(|sentence| match sentence {
+                        ClientSentences::RespondUps {
+                            ups_name,
+                            description,
+                        } => Some(Ok((ups_name, description))),
+                        ClientSentences::EndListUps {} => None,
+                        _ => Some(Err(NutError::UnexpectedResponse.into())),
+                    })
+                    .collect()
+            }
+        }
+    }
+
+    /// Queries a list of UPS devices, keeping only those whose name starts with `prefix`.
+    ///
+    /// The NUT protocol has no server-side filter, so this performs a full `LIST UPS`.
+    pub async fn list_ups_filtered(
+        &mut self,
+        prefix: &str,
+    ) -> crate::Result<Vec<(String, String)>> {
+        let mut devices = self.list_ups().await?;
+        devices.retain(|(name, _)| name.starts_with(prefix));
+        Ok(devices)
+    }
+
+    /// Queries the variables of a UPS device, keeping only those whose name matches a glob
+    /// `pattern`, where `*` matches any characters (e.g. `battery.*`).
+    ///
+    /// The NUT protocol has no server-side filter, so this performs a full `LIST VAR`.
+    pub async fn list_vars_matching(
+        &mut self,
+        ups_name: &str,
+        pattern: &str,
+    ) -> crate::Result<Vec<Variable>> {
+        let mut vars = self.list_vars(ups_name).await?;
+        vars.retain(|var| glob_matches(pattern, var.name()));
+        Ok(vars)
+    }
+
+    /// Queries the clients connected to every UPS device, as `(ups_name, client_ip)` pairs.
+    pub async fn list_all_clients(&mut self) -> crate::Result<Vec<(String, String)>> {
+        let mut clients = Vec::new();
+        for (ups_name, _) in self.list_ups().await? {
+            for client_ip in self.list_clients(&ups_name).await? {
+                clients.push((ups_name.clone(), client_ip));
+            }
+        }
+        Ok(clients)
+    }
+
+    /// Queries the mutable variables of a UPS device, along with their type definition
+    /// and allowed values (enum or range, as applicable).
+    pub async fn list_editable(&mut self, ups_name: &str) -> crate::Result<Vec<EditableVar>> {
+        let vars = self.list_mutable_vars(ups_name).await?;
+        match self {
+            Self::Tcp(conn) => {
+                let queries: Vec<String> = vars
+                    .iter()
+                    .map(|var| Command::Get(&["TYPE", ups_name, var.name()]).to_string())
+                    .collect();
+                let definitions = conn
+                    .pipeline_responses(&queries)
+                    .await?
+                    .into_iter()
+                    .map(|row| row.and_then(|row| row.expect_type()))
+                    .collect::<crate::Result<Vec<VariableDefinition>>>()?;
+
+                // The enum values and ranges are listed in the order of the variables
+                let mut queries: Vec<[&str; 3]> = Vec::new();
+                for (var, definition) in vars.iter().zip(&definitions) {
+                    if definition.is_enum() {
+                        queries.push(["ENUM", ups_name, var.name()]);
+                    }
+                    if definition.is_range() {
+                        queries.push(["RANGE", ups_name, var.name()]);
+                    }
+                }
+                let mut lists = conn.pipeline_lists(&queries).await?.into_iter();
+                let mut next_list = || {
+                    lists
+                        .next()
+                        .unwrap_or_else(|| Err(NutError::UnexpectedResponse.into()))
+                };
+
+                let mut editable = Vec::with_capacity(vars.len());
+                for (var, definition) in vars.into_iter().zip(definitions) {
+                    let enum_values = if definition.is_enum() {
+                        next_list()?
+                            .iter()
+                            .map(Response::expect_enum)
+                            .collect::<crate::Result<Vec<String>>>()?
+                    } else {
+                        Vec::new()
+                    };
+                    let ranges = if definition.is_range() {
+                        next_list()?
+                            .iter()
+                            .map(Response::expect_range)
+                            .collect::<crate::Result<Vec<VariableRange>>>()?
+                    } else {
+                        Vec::new()
+                    };
+                    editable.push(EditableVar {
+                        name: var.name().to_owned(),
+                        value: var.value(),
+                        definition,
+                        enum_values,
+                        ranges,
+                    });
+                }
+                Ok(editable)
+            }
+        }
+    }
+
+    /// Waits until the status of a UPS device (`ups.status`) changes, and returns the new flags.
+    ///
+    /// NUT has no push mechanism, so the status is polled at the given interval.
+    /// The wait can be cancelled by dropping the future.
+    pub async fn wait_for_status_change(
+        &mut self,
+        ups_name: &str,
+        poll_interval: Duration,
+    ) -> crate::Result<HashSet<UpsStatusFlag>> {
+        let initial = self.get_ups_status(ups_name).await?;
+        loop {
+            tokio::time::sleep(poll_interval).await;
+            let status = self.get_ups_status(ups_name).await?;
+            if status != initial {
+                return Ok(status);
+            }
+        }
+    }
+
+    /// Polls a variable of a UPS device, and calls `callback` with its value initially and
+    /// whenever it changes (identical consecutive values are skipped).
+    ///
+    /// Polling stops when the callback returns `false`, or when the returned future is dropped
+    /// (note that dropping it mid-request poisons the connection, see [`NutError::Poisoned`]).
+    pub async fn watch_var<F>(
+        &mut self,
+        ups_name: &str,
+        variable: &str,
+        poll_interval: Duration,
+        mut callback: F,
+    ) -> crate::Result<()>
+    where
+        F: FnMut(&Variable) -> bool,
+    {
+        let mut last: Option<Variable> = None;
+        loop {
+            let var = self.get_var(ups_name, variable).await?;
+            if last.as_ref() != Some(&var) {
+                if !callback(&var) {
+                    return Ok(());
+                }
+                last = Some(var);
+            }
+            tokio::time::sleep(poll_interval).await;
+        }
+    }
+
+    /// Spawns a task that takes over the connection to poll the status flags of a UPS device,
+    /// and sends them on the returned channel initially and whenever they change.
+    ///
+    /// The task closes the connection and finishes when the receiver is dropped. If a request
+    /// fails, the task finishes with the error (and the channel is closed).
+    ///
+    /// This must be called from within a Tokio runtime.
+    pub fn spawn_status_monitor(
+        mut self,
+        ups_name: &str,
+        poll_interval: Duration,
+    ) -> (
+        tokio::task::JoinHandle<crate::Result<()>>,
+        tokio::sync::mpsc::Receiver<HashSet<UpsStatusFlag>>,
+    ) {
+        let ups_name = ups_name.to_owned();
+        let (sender, receiver) = tokio::sync::mpsc::channel(16);
+        let handle = tokio::spawn(async move {
+            let mut last: Option<HashSet<UpsStatusFlag>> = None;
+            loop {
+                let status = self.get_ups_status(&ups_name).await?;
+                if last.as_ref() != Some(&status) {
+                    if sender.send(status.clone()).await.is_err() {
+                        break;
+                    }
+                    last = Some(status);
+                }
+                // Wait for the next poll, unless the receiver is dropped in the meantime
+                if tokio::time::timeout(poll_interval, sender.closed())
+                    .await
+                    .is_ok()
+                {
+                    break;
+                }
+            }
+            self.close().await
+        });
+        (handle, receiver)
+    }
+
+    /// Queries the constraints on the values of a variable: its type, then its allowed
+    /// ranges and values, only if its type has them.
+    ///
+    /// This applies to read-only variables too, since some drivers give them a range
+    /// (e.g. diagnostic bounds).
+    pub async fn var_constraints(
+        &mut self,
+        ups_name: &str,
+        variable: &str,
+    ) -> crate::Result<VarConstraints> {
+        let definition = self.get_var_type(ups_name, variable).await?;
+        let ranges = if definition.is_range() {
+            self.list_var_range(ups_name, variable).await?
+        } else {
+            Vec::new()
+        };
+        let enums = if definition.is_enum() {
+            self.list_var_enum(ups_name, variable).await?
+        } else {
+            Vec::new()
+        };
+        Ok(VarConstraints {
+            definition,
+            ranges,
+            enums,
+        })
+    }
+
+    /// Queries one variable for a UPS device, returning `None` if the device doesn't
+    /// support it.
+    ///
+    /// Other errors (such as I/O, authentication or an unknown device) are still returned.
+    pub async fn try_get_var(
+        &mut self,
+        ups_name: &str,
+        variable: &str,
+    ) -> crate::Result<Option<Variable>> {
+        match self.get_var(ups_name, variable).await {
+            Ok(var) => Ok(Some(var)),
+            Err(ClientError::Nut(NutError::VarNotSupported)) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Queries the allowed values of every enumerated variable of a UPS device.
+    ///
+    /// The types (and then the values) of all variables are requested in pipelined batches,
+    /// rather than waiting for each response in turn.
+    pub async fn list_all_enums(
+        &mut self,
+        ups_name: &str,
+    ) -> crate::Result<HashMap<String, Vec<String>>> {
+        let names: Vec<String> = self
+            .list_vars(ups_name)
+            .await?
+            .iter()
+            .map(|var| var.name().to_owned())
+            .collect();
+
+        match self {
+            Self::Tcp(conn) => {
+                let queries: Vec<String> = names
+                    .iter()
+                    .map(|name| Command::Get(&["TYPE", ups_name, name]).to_string())
+                    .collect();
+                let definitions = conn.pipeline_responses(&queries).await?;
+                let mut enum_names = Vec::new();
+                for (name, definition) in names.into_iter().zip(definitions) {
+                    if definition.and_then(|row| row.expect_type())?.is_enum() {
+                        enum_names.push(name);
+                    }
+                }
+
+                let queries: Vec<[&str; 3]> = enum_names
+                    .iter()
+                    .map(|name| ["ENUM", ups_name, name])
+                    .collect();
+                let lists = conn.pipeline_lists(&queries).await?;
+                let mut enums = HashMap::new();
+                for (name, list) in enum_names.into_iter().zip(lists) {
+                    let values = list?
+                        .iter()
+                        .map(Response::expect_enum)
+                        .collect::<crate::Result<Vec<String>>>()?;
+                    enums.insert(name, values);
+                }
+                Ok(enums)
+            }
+        }
+    }
+
+    /// Queries one variable for a UPS device along with its description.
+    ///
+    /// Both queries are sent at once (pipelined), saving a round trip over calling
+    /// `get_var` and `get_var_description`.
+    pub async fn get_var_detailed(
+        &mut self,
+        ups_name: &str,
+        variable: &str,
+    ) -> crate::Result<(Variable, String)> {
+        match self {
+            Self::Tcp(conn) => {
+                conn.write_lines(&[
+                    Command::Get(&["VAR", ups_name, variable]).to_string(),
+                    Command::Get(&["DESC", ups_name, variable]).to_string(),
+                ])
+                .await?;
+                let [var, desc] = <[_; 2]>::try_from(conn.read_responses(2).await?)
+                    .map_err(|_| NutError::UnexpectedResponse)?;
+                let var = var?.expect_var(ups_name)?;
+                let desc = desc?.expect_desc()?;
+                Ok((var, desc))
+            }
+        }
+    }
+
+    /// Guesses whether the primary `upsmon` (formerly "master") of a UPS device is logged in.
+    ///
+    /// NUT doesn't tell the role of logged-in clients, so this is a heuristic: the primary runs
+    /// on the machine the UPS is attached to, which is usually the one running upsd. It is
+    /// considered connected if any client (see `list_clients`) is logged in from a loopback
+    /// address. This is wrong if upsd runs on a different machine than the primary, or if
+    /// another local client (e.g. a secondary `upsmon`) is logged in. Use `get_num_logins` and
+    /// `list_clients` directly to apply other rules.
+    pub async fn is_primary_connected(&mut self, ups_name: &str) -> crate::Result<bool> {
+        if self.get_num_logins(ups_name).await? == 0 {
+            return Ok(false);
+        }
+        let clients = self.list_clients(ups_name).await?;
+        Ok(clients
+            .iter()
+            .any(|addr| crate::util::is_loopback_client(addr)))
+    }
+
+    /// Queries one variable for a UPS device.
+    ///
+    /// If enabled (see
+    /// [`ConfigBuilder::with_auto_login_on_access_denied`](crate::ConfigBuilder::with_auto_login_on_access_denied)),
+    /// logs in to the device and retries once when access is denied. If logging in fails
+    /// (e.g. `ALREADY-LOGGED-IN` to another device), its error is returned instead.
+    pub async fn get_var(&mut self, ups_name: &str, variable: &str) -> crate::Result<Variable> {
+        let result = self.query_var(ups_name, variable).await;
+        if self.should_login_after(&result) {
+            self.login_ups(ups_name).await?;
+            return self.query_var(ups_name, variable).await;
+        }
+        result
+    }
+
+    /// Queries the list of variables for a UPS device.
+    ///
+    /// If enabled (see
+    /// [`ConfigBuilder::with_auto_login_on_access_denied`](crate::ConfigBuilder::with_auto_login_on_access_denied)),
+    /// logs in to the device and retries once when access is denied. If logging in fails
+    /// (e.g. `ALREADY-LOGGED-IN` to another device), its error is returned instead.
+    pub async fn list_vars(&mut self, ups_name: &str) -> crate::Result<Vec<Variable>> {
+        let result = self.query_list_vars(ups_name).await;
+        if self.should_login_after(&result) {
+            self.login_ups(ups_name).await?;
+            return self.query_list_vars(ups_name).await;
+        }
+        result
+    }
+
+    /// Whether the result is `ACCESS-DENIED`, and logging in to retry is enabled.
+    fn should_login_after<T>(&self, result: &crate::Result<T>) -> bool {
+        let config = match self {
+            Self::Tcp(conn) => conn.config(),
+        };
+        config.login_on_access_denied
+            && config
+                .auth
+                .as_ref()
+                .is_some_and(|auth| auth.password.is_some())
+            && matches!(result, Err(ClientError::Nut(NutError::AccessDenied)))
+    }
+
+    /// Queries the active alarms of a UPS device (`ups.alarm`), which are signaled by the
+    /// [`UpsStatusFlag::Alarm`] status flag.
+    ///
+    /// Returns an empty list if there is no alarm.
+    pub async fn get_alarms(&mut self, ups_name: &str) -> crate::Result<Vec<String>> {
+        Ok(self
+            .try_get_var(ups_name, key::UPS_ALARM)
+            .await?
+            .map(|var| crate::var::parse_alarms(&var.value()))
+            .unwrap_or_default())
+    }
+
+    /// Queries the protocol features supported by the server, derived from `HELP`, the
+    /// server version and the network version.
+    ///
+    /// The result is cached on the connection, so the server is only queried once.
+    pub async fn capabilities(&mut self) -> crate::Result<Capabilities> {
+        match self {
+            Self::Tcp(conn) => {
+                if let Some(capabilities) = &conn.capabilities {
+                    return Ok(capabilities.clone());
+                }
+            }
+        }
+        let commands = self.help().await?;
+        let version = self.known_server_version().await?;
+        let capabilities = Capabilities::new(commands, &version, self.network_version());
+        match self {
+            Self::Tcp(conn) => conn.capabilities = Some(capabilities.clone()),
+        }
+        Ok(capabilities)
+    }
+
+    /// The server version probed when connecting, or queried (and kept) if it wasn't.
+    async fn known_server_version(&mut self) -> crate::Result<ServerVersion> {
+        match self {
+            Self::Tcp(conn) => {
+                if let Some(version) = &conn.server_version {
+                    return Ok(version.clone());
+                }
+            }
+        }
+        let version = self.get_server_version_parsed().await?;
+        match self {
+            Self::Tcp(conn) => conn.server_version = Some(version.clone()),
+        }
+        Ok(version)
+    }
+
+    /// Measures the round-trip time of a minimal request (`NETVER`) to the server.
+    pub async fn ping_latency(&mut self) -> crate::Result<Duration> {
+        let start = Instant::now();
+        self.get_network_version().await?;
+        Ok(start.elapsed())
+    }
+
+    /// Queries the [`CommonMetrics`] of a UPS device: battery charge, load, input voltage
+    /// and status.
+    ///
+    /// The variables are requested at once (pipelined). Variables that the device does not
+    /// support are left out of the metrics, rather than failing the whole query.
+    pub async fn common_metrics(&mut self, ups_name: &str) -> crate::Result<CommonMetrics> {
+        let vars = self
+            .get_supported_vars(ups_name, &CommonMetrics::KEYS)
+            .await?;
+        Ok(vars.into_iter().collect())
+    }
+
+    /// Queries the [`DeviceIdentity`] of a UPS device: model, manufacturer, serial number,
+    /// type and location.
+    ///
+    /// The variables are requested at once (pipelined). Variables that the device does not
+    /// support are left as `None`, rather than failing the whole query.
+    pub async fn device_identity(&mut self, ups_name: &str) -> crate::Result<DeviceIdentity> {
+        let vars = self
+            .get_supported_vars(ups_name, &DeviceIdentity::KEYS)
+            .await?;
+        Ok(vars.into_iter().collect())
+    }
+
+    /// Queries several variables at once (pipelined), leaving out those that the device
+    /// does not support.
+    async fn get_supported_vars(
+        &mut self,
+        ups_name: &str,
+        names: &[&str],
+    ) -> crate::Result<Vec<Variable>> {
+        match self {
+            Self::Tcp(conn) => {
+                let queries: Vec<String> = names
+                    .iter()
+                    .map(|name| Command::Get(&["VAR", ups_name, name]).to_string())
+                    .collect();
+                let responses = conn.pipeline_responses(&queries).await?;
+                let mut supported = Vec::with_capacity(responses.len());
+                for response in responses {
+                    match response.and_then(|row| row.expect_var(ups_name)) {
+                        Ok(var) => supported.push(var),
+                        Err(ClientError::Nut(NutError::VarNotSupported)) => {}
+                        Err(e) => return Err(e),
+                    }
+                }
+                Ok(supported)
+            }
+        }
+    }
+
+    /// Asserts primary-level (formerly "master") access to a UPS device, which the logged-in
+    /// user must have been granted (`upsmon primary` in `upsd.users`).
+    ///
+    /// This sends `PRIMARY` to servers from NUT 2.8 onwards, and `MASTER` to older (or
+    /// unrecognized) servers.
+    #[doc(alias = "master")]
+    pub async fn primary(&mut self, ups_name: &str) -> crate::Result<()> {
+        let version = self.known_server_version().await?;
+        match self {
+            Self::Tcp(conn) => {
+                if version.is_at_least((2, 8, 0)) {
+                    conn.write_cmd(Command::Primary(ups_name)).await?;
+                } else {
+                    conn.write_cmd(Command::Master(ups_name)).await?;
+                }
+                conn.read_response().await?.expect_ok()?;
+                Ok(())
+            }
+        }
+    }
+
+    /// Queries a snapshot of all the variables of a UPS device, which can be looked up by name.
+    pub async fn snapshot(&mut self, ups_name: &str) -> crate::Result<DeviceSnapshot> {
+        Ok(self.list_vars(ups_name).await?.into_iter().collect())
+    }
+
+    /// Queries the complete schema of a UPS device: every variable (with its type,
+    /// description and allowed values) and every instant command (with its description).
+    ///
+    /// This performs several requests per variable and command.
+    pub async fn schema(&mut self, ups_name: &str) -> crate::Result<DeviceSchema> {
+        let mut variables = Vec::new();
+        for var in self.list_vars(ups_name).await? {
+            let description = self.get_var_description(ups_name, var.name()).await?;
+            let definition = self.get_var_type(ups_name, var.name()).await?;
+            let enum_values = if definition.is_enum() {
+                self.list_var_enum(ups_name, var.name()).await?
+            } else {
+                Vec::new()
+            };
+            let ranges = if definition.is_range() {
+                self.list_var_range(ups_name, var.name()).await?
+            } else {
+                Vec::new()
+            };
+            variables.push(VariableSchema {
+                name: var.name().to_owned(),
+                value: var.value(),
+                description,
+                types: definition.type_names(),
+                enum_values,
+                ranges,
+            });
+        }
+
+        let mut commands = Vec::new();
+        for name in self.list_commands(ups_name).await? {
+            let description = self.get_command_description(ups_name, &name).await?;
+            commands.push(CommandSchema { name, description });
+        }
+
+        Ok(DeviceSchema {
+            ups_name: ups_name.to_owned(),
+            variables,
+            commands,
+        })
+    }
+
+    /// Queries the state of the whole server: its versions, and the description, number of
+    /// logins and complete schema (see [`Connection::schema`](crate::tokio::Connection::schema)) of every UPS device.
+    ///
+    /// This performs many requests (several per variable), so it is best suited for
+    /// inventories rather than frequent polling.
+    pub async fn full_report(&mut self) -> crate::Result<Report> {
+        let server_version = self.get_server_version().await?;
+        let network_version = self.get_network_version().await?;
+        let mut devices = Vec::new();
+        for (ups_name, description) in self.list_ups().await? {
+            let num_logins = self.get_num_logins(&ups_name).await?;
+            let schema = self.schema(&ups_name).await?;
+            devices.push(DeviceReport {
+                description,
+                num_logins,
+                schema,
+            });
+        }
+        Ok(Report {
+            server_version,
+            network_version,
+            devices,
+        })
+    }
+
+    /// Sends a raw command line to the server, and returns the words of each response line.
+    ///
+    /// This is an escape hatch for protocol features that aren't covered by the typed API.
+    /// The line is sent as-is (a line break is appended). A single response line is read,
+    /// unless the server begins a list (`BEGIN LIST ...`), in which case all lines are read
+    /// until the matching `END`. An `ERR` response is returned as the corresponding error.
+    ///
+    /// Use with care: sending a command that produces an unexpected number of response
+    /// lines will leave the connection in an inconsistent state.
+    pub async fn raw(&mut self, line: &str) -> crate::Result<Vec<Vec<String>>> {
+        match self {
+            Self::Tcp(conn) => {
+                conn.write_line(line).await?;
+                conn.read_raw_response().await
+            }
+        }
+    }
+
+    /// Sends a command built at runtime, and returns the words of each response line.
+    ///
+    /// The response is read as with [`raw`](Self::raw).
+    pub async fn send_command(&mut self, cmd: &OwnedCommand) -> crate::Result<Vec<Vec<String>>> {
+        self.raw(&cmd.to_string()).await
+    }
+}
+
+#[cfg(feature = "write")]
+impl crate::blocking::Connection {
+    /// Runs an instant command on the UPS, with an optional parameter.
+    pub fn run_command(
+        &mut self,
+        ups_name: &str,
+        cmd: &str,
+        param: Option<&str>,
+    ) -> crate::Result<()> {
+        match self {
+            Self::Tcp(conn) => {
+                conn.write_cmd(Command::Run(ups_name, cmd, param))?;
+                conn.read_response()?.expect_ok()?;
+                Ok(())
+            }
+        }
+    }
+
+    /// Sets the value of a variable on the UPS.
+    pub fn set_var(&mut self, ups_name: &str, variable: &str, value: &str) -> crate::Result<()> {
+        self.set_var_tracked(ups_name, variable, value)?;
+        Ok(())
+    }
+
+    /// Sets the value of a variable on the UPS, returning the tracking id of the request
+    /// if the server has tracking enabled (`OK TRACKING <id>`).
+    pub fn set_var_tracked(
+        &mut self,
+        ups_name: &str,
+        variable: &str,
+        value: &str,
+    ) -> crate::Result<Option<String>> {
+        match self {
+            Self::Tcp(conn) => {
+                conn.write_cmd(Command::Set(ups_name, variable, value))?;
+                conn.read_response()?.expect_ok_tracking()
+            }
+        }
+    }
+
+    /// Sets several variables on the UPS, one after the other, reporting which ones were set.
+    ///
+    /// NUT has no transactions, so this is best-effort: a variable that is rejected (e.g. an
+    /// invalid value) doesn't stop the others, and those already set are not rolled back.
+    /// If the connection fails, the remaining variables are skipped. Errors are recorded in
+    /// the report rather than returned.
+    pub fn set_vars(&mut self, ups_name: &str, vars: &[(&str, &str)]) -> crate::Result<SetReport> {
+        let mut report = SetReport::default();
+        let mut vars = vars.iter();
+        for (variable, value) in vars.by_ref() {
+            match self.set_var(ups_name, variable, value) {
+                Ok(()) => report.applied.push(variable.to_string()),
+                Err(e) => {
+                    // After these, the connection can't be trusted for further requests
+                    let broken = matches!(
+                        e,
+                        ClientError::Io(_)
+                            | ClientError::Nut(
+                                NutError::Timeout
+                                    | NutError::Poisoned
+                                    | NutError::UnexpectedResponse
+                            )
+                    );
+                    report.failed.push((variable.to_string(), e));
+                    if broken {
+                        break;
+                    }
+                }
+            }
+        }
+        report
+            .skipped
+            .extend(vars.map(|(variable, _)| variable.to_string()));
+        Ok(report)
+    }
+
+    /// Sets the forced shutdown flag on the UPS (`FSD`), which makes upsmon shut down the
+    /// systems it powers, then turn off the UPS.
+    ///
+    /// This requires primary-level access, and can't be undone: the [`FsdConfirm`] token
+    /// must be created explicitly to show the intent.
+    pub fn fsd_with_confirmation(
+        &mut self,
+        ups_name: &str,
+        _confirm: FsdConfirm,
+    ) -> crate::Result<()> {
+        self.fsd(ups_name)
+    }
+
+    fn fsd(&mut self, ups_name: &str) -> crate::Result<()> {
+        match self {
+            Self::Tcp(conn) => {
+                conn.write_cmd(Command::Fsd(ups_name))?;
+                conn.read_response()?.expect_ok()?;
+                Ok(())
+            }
+        }
+    }
+
+    /// Turns off the load of the UPS, then returns when power is back, after the given
+    /// delay (`shutdown.return`). The delay is sent in whole seconds, rounded up;
+    /// a zero delay runs the command immediately.
+    pub fn shutdown_return(&mut self, ups_name: &str, delay: Duration) -> crate::Result<()> {
+        let delay = delay_secs(delay);
+        self.run_command(ups_name, "shutdown.return", Some(&delay))
+    }
+
+    /// Turns off the load of the UPS after the given delay (`load.off.delay`).
+    /// The delay is sent in whole seconds, rounded up;
+    /// a zero delay runs the command immediately.
+    pub fn load_off_delay(&mut self, ups_name: &str, delay: Duration) -> crate::Result<()> {
+        let delay = delay_secs(delay);
+        self.run_command(ups_name, "load.off.delay", Some(&delay))
+    }
+}
+
+#[cfg(all(feature = "write", feature = "async"))]
+impl crate::tokio::Connection {
+    /// Runs an instant command on the UPS, with an optional parameter.
+    pub async fn run_command(
+        &mut self,
+        ups_name: &str,
+        cmd: &str,
+        param: Option<&str>,
+    ) -> crate::Result<()> {
+        match self {
+            Self::Tcp(conn) => {
+                conn.write_cmd(Command::Run(ups_name, cmd, param)).await?;
+                conn.read_response().await?.expect_ok()?;
+                Ok(())
+            }
+        }
+    }
+
+    /// Sets the value of a variable on the UPS.
+    pub async fn set_var(
+        &mut self,
+        ups_name: &str,
+        variable: &str,
+        value: &str,
+    ) -> crate::Result<()> {
+        self.set_var_tracked(ups_name, variable, value).await?;
+        Ok(())
+    }
+
+    /// Sets the value of a variable on the UPS, returning the tracking id of the request
+    /// if the server has tracking enabled (`OK TRACKING <id>`).
+    pub async fn set_var_tracked(
+        &mut self,
+        ups_name: &str,
+        variable: &str,
+        value: &str,
+    ) -> crate::Result<Option<String>> {
+        match self {
+            Self::Tcp(conn) => {
+                conn.write_cmd(Command::Set(ups_name, variable, value))
+                    .await?;
+                conn.read_response().await?.expect_ok_tracking()
+            }
+        }
+    }
+
+    /// Sets several variables on the UPS, one after the other, reporting which ones were set.
+    ///
+    /// NUT has no transactions, so this is best-effort: a variable that is rejected (e.g. an
+    /// invalid value) doesn't stop the others, and those already set are not rolled back.
+    /// If the connection fails, the remaining variables are skipped. Errors are recorded in
+    /// the report rather than returned.
+    pub async fn set_vars(
+        &mut self,
+        ups_name: &str,
+        vars: &[(&str, &str)],
+    ) -> crate::Result<SetReport> {
+        let mut report = SetReport::default();
+        let mut vars = vars.iter();
+        for (variable, value) in vars.by_ref() {
+            match self.set_var(ups_name, variable, value).await {
+                Ok(()) => report.applied.push(variable.to_string()),
+                Err(e) => {
+                    // After these, the connection can't be trusted for further requests
+                    let broken = matches!(
+                        e,
+                        ClientError::Io(_)
+                            | ClientError::Nut(
+                                NutError::Timeout
+                                    | NutError::Poisoned
+                                    | NutError::UnexpectedResponse
+                            )
+                    );
+                    report.failed.push((variable.to_string(), e));
+                    if broken {
+                        break;
+                    }
+                }
+            }
+        }
+        report
+            .skipped
+            .extend(vars.map(|(variable, _)| variable.to_string()));
+        Ok(report)
+    }
+
+    /// Sets the forced shutdown flag on the UPS (`FSD`), which makes upsmon shut down the
+    /// systems it powers, then turn off the UPS.
+    ///
+    /// This requires primary-level access, and can't be undone: the [`FsdConfirm`] token
+    /// must be created explicitly to show the intent.
+    pub async fn fsd_with_confirmation(
+        &mut self,
+        ups_name: &str,
+        _confirm: FsdConfirm,
+    ) -> crate::Result<()> {
+        self.fsd(ups_name).await
+    }
+
+    async fn fsd(&mut self, ups_name: &str) -> crate::Result<()> {
+        match self {
+            Self::Tcp(conn) => {
+                conn.write_cmd(Command::Fsd(ups_name)).await?;
+                conn.read_response().await?.expect_ok()?;
+                Ok(())
+            }
+        }
+    }
+
+    /// Turns off the load of the UPS, then returns when power is back, after the given
+    /// delay (`shutdown.return`). The delay is sent in whole seconds, rounded up;
+    /// a zero delay runs the command immediately.
+    pub async fn shutdown_return(&mut self, ups_name: &str, delay: Duration) -> crate::Result<()> {
+        let delay = delay_secs(delay);
+        self.run_command(ups_name, "shutdown.return", Some(&delay))
+            .await
+    }
+
+    /// Turns off the load of the UPS after the given delay (`load.off.delay`).
+    /// The delay is sent in whole seconds, rounded up;
+    /// a zero delay runs the command immediately.
+    pub async fn load_off_delay(&mut self, ups_name: &str, delay: Duration) -> crate::Result<()> {
+        let delay = delay_secs(delay);
+        self.run_command(ups_name, "load.off.delay", Some(&delay))
+            .await
+    }
+}
+
+/// Formats the delay of an instant command in whole seconds, rounded up so that a
+/// sub-second delay isn't sent as an immediate one. Only a zero delay is sent as `0`.
+#[cfg(feature = "write")]
+fn delay_secs(delay: Duration) -> String {
+    let secs = delay.as_secs() + (delay.subsec_nanos() > 0) as u64;
+    secs.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_owned_command() {
+        let query = vec![String::from("VAR"), String::from("nutdev")];
+        let cmd = OwnedCommand::list(query.clone());
+        assert_eq!(cmd.name(), "LIST");
+        assert_eq!(cmd.args(), query.as_slice());
+        assert_eq!(cmd.to_string(), "LIST VAR nutdev");
+        assert_eq!(cmd, OwnedCommand::from(Command::List(&["VAR", "nutdev"])));
+        assert_eq!(
+            OwnedCommand::get(vec!["DESC".into(), "nutdev".into(), "my var".into()]).to_string(),
+            "GET DESC nutdev \"my var\""
+        );
+    }
+
+    #[test]
+    fn test_parse_err_codes() {
+        let codes = [
+            ("ACCESS-DENIED", NutError::AccessDenied),
+            ("UNKNOWN-UPS", NutError::UnknownUps),
+            ("VAR-NOT-SUPPORTED", NutError::VarNotSupported),
+            ("CMD-NOT-SUPPORTED", NutError::CmdNotSupported),
+            ("INVALID-ARGUMENT", NutError::InvalidArgument),
+            ("INSTCMD-FAILED", NutError::InstCmdFailed),
+            ("SET-FAILED", NutError::SetFailed),
+            ("READONLY", NutError::ReadOnly),
+            ("TOO-LONG", NutError::TooLong),
+            ("FEATURE-NOT-SUPPORTED", NutError::FeatureNotSupported),
+            ("FEATURE-NOT-CONFIGURED", NutError::FeatureNotConfigured),
+            ("ALREADY-SSL-MODE", NutError::AlreadySslMode),
+            ("DRIVER-NOT-CONNECTED", NutError::DriverNotConnected),
+            ("DATA-STALE", NutError::DataStale),
+            ("ALREADY-LOGGED-IN", NutError::AlreadyLoggedIn),
+            ("INVALID-PASSWORD", NutError::InvalidPassword),
+            ("ALREADY-SET-PASSWORD", NutError::AlreadySetPassword),
+            ("INVALID-USERNAME", NutError::InvalidUsername),
+            ("ALREADY-SET-USERNAME", NutError::AlreadySetUsername),
+            ("USERNAME-REQUIRED", NutError::UsernameRequired),
+            ("PASSWORD-REQUIRED", NutError::PasswordRequired),
+            ("UNKNOWN-COMMAND", NutError::UnknownCommand),
+            ("INVALID-VALUE", NutError::InvalidValue),
+        ];
+        for (code, expected) in codes {
+            match Response::from_args(vec!["ERR".into(), code.into()]) {
+                Err(ClientError::Nut(err)) => {
+                    assert_eq!(format!("{:?}", err), format!("{:?}", expected), "{}", code)
+                }
+                _ => panic!("expected an error for {}", code),
+            }
+        }
+
+        match Response::from_args(vec!["ERR".into(), "SOMETHING-NEW".into()]) {
+            Err(ClientError::Nut(NutError::Generic(msg))) => assert!(msg.contains("SOMETHING-NEW")),
+            _ => panic!("expected a generic error"),
+        }
+    }
 }
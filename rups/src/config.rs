@@ -1,7 +1,7 @@
 use core::fmt;
 use std::convert::{TryFrom, TryInto};
 use std::net::{SocketAddr, ToSocketAddrs};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use crate::ClientError;
 
@@ -14,6 +14,18 @@ pub enum Host {
 }
 
 impl Host {
+    /// Creates a TCP host from a resolved address, along with the DNS hostname it was
+    /// resolved from.
+    ///
+    /// The hostname is used to verify the server certificate in strict SSL mode, which is
+    /// not possible for a host created from an IP address alone (see `From<SocketAddr>`).
+    pub fn tcp_with_hostname<T: Into<String>>(addr: SocketAddr, hostname: T) -> Self {
+        Self::Tcp(TcpHost {
+            hostname: hostname.into(),
+            addr,
+        })
+    }
+
     /// Returns the hostname as given, if any.
     pub fn hostname(&self) -> Option<String> {
         match self {
@@ -31,6 +43,8 @@ impl Default for Host {
     }
 }
 
+/// Creates a TCP host from an address alone. Its hostname is the IP address, which cannot be used
+/// for strict SSL: use [`Host::tcp_with_hostname`] instead.
 impl From<SocketAddr> for Host {
     fn from(addr: SocketAddr) -> Self {
         let hostname = addr.ip().to_string();
@@ -38,6 +52,20 @@ impl From<SocketAddr> for Host {
     }
 }
 
+impl fmt::Display for Host {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Host::Tcp(host)
+                if host.hostname.trim_start_matches('[').trim_end_matches(']')
+                    == host.addr.ip().to_string() =>
+            {
+                write!(f, "{}", host.addr)
+            }
+            Host::Tcp(host) => write!(f, "{} ({})", host.hostname, host.addr),
+        }
+    }
+}
+
 /// A TCP address, preserving the original DNS hostname if any.
 #[derive(Clone, Debug)]
 pub struct TcpHost {
@@ -45,12 +73,59 @@ pub struct TcpHost {
     pub(crate) addr: SocketAddr,
 }
 
+impl TcpHost {
+    /// Returns the address to connect to, in the given address family.
+    ///
+    /// If the address given for this host is in another family, the hostname is resolved
+    /// again to find one in the requested family.
+    pub(crate) fn resolve(&self, family: AddressFamily) -> crate::Result<SocketAddr> {
+        if family.matches(&self.addr) {
+            return Ok(self.addr);
+        }
+        let hostname = self.hostname.trim_start_matches('[').trim_end_matches(']');
+        (hostname, self.addr.port())
+            .to_socket_addrs()?
+            .find(|addr| family.matches(addr))
+            .ok_or_else(|| {
+                ClientError::Io(std::io::Error::new(
+                    std::io::ErrorKind::AddrNotAvailable,
+                    format!("no {:?} address for {}", family, self.hostname),
+                ))
+            })
+    }
+}
+
+/// The IP address family to use when connecting.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum AddressFamily {
+    /// Any family: the first address the host resolves to.
+    #[default]
+    Any,
+    /// IPv4 only.
+    V4,
+    /// IPv6 only.
+    V6,
+}
+
+impl AddressFamily {
+    /// Whether the address is in this family.
+    pub fn matches(&self, addr: &SocketAddr) -> bool {
+        match self {
+            Self::Any => true,
+            Self::V4 => addr.is_ipv4(),
+            Self::V6 => addr.is_ipv6(),
+        }
+    }
+}
+
 impl TryFrom<(String, u16)> for Host {
     type Error = ClientError;
 
     fn try_from(hostname_port: (String, u16)) -> Result<Self, Self::Error> {
-        let (hostname, _) = hostname_port.clone();
-        let addr = hostname_port
+        let (hostname, port) = hostname_port;
+        // IPv6 addresses may be bracketed; scoped addresses (e.g. `fe80::1%eth0`)
+        // are resolved by the system, which keeps the scope id.
+        let addr = (hostname.trim_start_matches('[').trim_end_matches(']'), port)
             .to_socket_addrs()
             .map_err(ClientError::Io)?
             .next()
@@ -78,6 +153,21 @@ impl Auth {
     pub fn new(username: String, password: Option<String>) -> Self {
         Auth { username, password }
     }
+
+    /// Reads authentication credentials from the environment: the username from `NUT_USER`,
+    /// and optionally the password from `NUT_PASSWORD`.
+    ///
+    /// Returns `None` if `NUT_USER` is not set (or is not valid unicode).
+    pub fn from_env() -> Option<Self> {
+        Self::from_vars(|key| std::env::var(key).ok())
+    }
+
+    /// Reads authentication credentials from `NUT_USER` and `NUT_PASSWORD`, as given by `var`.
+    pub(crate) fn from_vars<F: Fn(&str) -> Option<String>>(var: F) -> Option<Self> {
+        let username = var("NUT_USER")?;
+        let password = var("NUT_PASSWORD");
+        Some(Auth::new(username, password))
+    }
 }
 
 impl fmt::Debug for Auth {
@@ -98,6 +188,20 @@ pub struct Config {
     pub(crate) ssl: bool,
     pub(crate) ssl_insecure: bool,
     pub(crate) debug: bool,
+    pub(crate) auto_login: bool,
+    pub(crate) login_on_access_denied: bool,
+    pub(crate) read_only: bool,
+    pub(crate) ssl_pinned_cert: Option<[u8; 32]>,
+    pub(crate) bind_address: Option<SocketAddr>,
+    pub(crate) tcp_nodelay: bool,
+    pub(crate) probe_version: bool,
+    pub(crate) deadline: Option<Instant>,
+    pub(crate) read_timeout: Option<Duration>,
+    pub(crate) write_timeout: Option<Duration>,
+    pub(crate) address_family: AddressFamily,
+    pub(crate) fallback_hosts: Vec<Host>,
+    #[cfg(feature = "ssl")]
+    pub(crate) ssl_client_config: Option<crate::ssl::CustomClientConfig>,
 }
 
 impl Config {
@@ -117,7 +221,41 @@ impl Config {
             ssl,
             ssl_insecure,
             debug,
+            auto_login: true,
+            login_on_access_denied: false,
+            read_only: false,
+            ssl_pinned_cert: None,
+            bind_address: None,
+            tcp_nodelay: true,
+            probe_version: true,
+            deadline: None,
+            read_timeout: None,
+            write_timeout: None,
+            address_family: AddressFamily::Any,
+            fallback_hosts: Vec::new(),
+            #[cfg(feature = "ssl")]
+            ssl_client_config: None,
+        }
+    }
+}
+
+/// Summarizes the configuration for messages, e.g. `localhost (127.0.0.1:3493), user admin, SSL,
+/// timeout 5s`. Passwords are never shown.
+impl fmt::Display for Config {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.host)?;
+        match &self.auth {
+            Some(auth) => write!(f, ", user {}", auth.username)?,
+            None => write!(f, ", no login")?,
         }
+        if self.ssl {
+            if self.ssl_insecure {
+                write!(f, ", insecure SSL")?;
+            } else {
+                write!(f, ", SSL")?;
+            }
+        }
+        write!(f, ", timeout {:?}", self.timeout)
     }
 }
 
@@ -130,6 +268,20 @@ pub struct ConfigBuilder {
     ssl: Option<bool>,
     ssl_insecure: Option<bool>,
     debug: Option<bool>,
+    auto_login: Option<bool>,
+    login_on_access_denied: Option<bool>,
+    read_only: Option<bool>,
+    ssl_pinned_cert: Option<[u8; 32]>,
+    bind_address: Option<SocketAddr>,
+    tcp_nodelay: Option<bool>,
+    probe_version: Option<bool>,
+    deadline: Option<Instant>,
+    read_timeout: Option<Duration>,
+    write_timeout: Option<Duration>,
+    address_family: Option<AddressFamily>,
+    fallback_hosts: Vec<Host>,
+    #[cfg(feature = "ssl")]
+    ssl_client_config: Option<crate::ssl::CustomClientConfig>,
 }
 
 impl ConfigBuilder {
@@ -138,12 +290,69 @@ impl ConfigBuilder {
         ConfigBuilder::default()
     }
 
+    /// Initializes a builder from environment variables:
+    ///
+    /// * `NUT_HOST`: the upsd hostname (defaults to `localhost`)
+    /// * `NUT_PORT`: the upsd port (defaults to `3493`)
+    /// * `NUT_USER` and `NUT_PASSWORD`: the credentials (see [`Auth::from_env`])
+    /// * `NUT_SSL`: whether to enable SSL (defaults to `false`)
+    /// * `NUT_SSL_INSECURE`: whether to turn off SSL verification (defaults to `false`)
+    ///
+    /// Booleans may be `true`/`false`, `yes`/`no`, `on`/`off` or `1`/`0`.
+    /// Fails if a variable has a malformed value, or if the host cannot be resolved.
+    pub fn from_env() -> crate::Result<Self> {
+        Self::from_vars(|key| std::env::var(key).ok())
+    }
+
+    fn from_vars<F: Fn(&str) -> Option<String>>(var: F) -> crate::Result<Self> {
+        let parse_bool = |key: &str| -> crate::Result<Option<bool>> {
+            var(key)
+                .map(|value| match value.to_ascii_lowercase().as_str() {
+                    "1" | "true" | "yes" | "on" => Ok(true),
+                    "0" | "false" | "no" | "off" => Ok(false),
+                    _ => Err(ClientError::generic(format!(
+                        "Invalid boolean in {}: {}",
+                        key, value
+                    ))),
+                })
+                .transpose()
+        };
+
+        let hostname = var("NUT_HOST").unwrap_or_else(|| String::from("localhost"));
+        let port = match var("NUT_PORT") {
+            Some(port) => port
+                .parse::<u16>()
+                .map_err(|_| ClientError::generic(format!("Invalid port in NUT_PORT: {}", port)))?,
+            None => 3493,
+        };
+        let auth = Auth::from_vars(&var);
+
+        Ok(ConfigBuilder {
+            host: Some((hostname, port).try_into()?),
+            auth,
+            ssl: parse_bool("NUT_SSL")?,
+            ssl_insecure: parse_bool("NUT_SSL_INSECURE")?,
+            ..ConfigBuilder::default()
+        })
+    }
+
     /// Sets the connection host, such as the TCP address and port.
     pub fn with_host(mut self, host: Host) -> Self {
         self.host = Some(host);
         self
     }
 
+    /// Sets hosts to fall back to, in order, when connecting to the main host fails
+    /// (e.g. for redundant upsd servers). The first host that accepts the connection
+    /// (and the login, if any) is used.
+    ///
+    /// This only applies when connecting: once connected, errors are returned as usual,
+    /// and the connection does not switch to another host.
+    pub fn with_fallback_hosts(mut self, fallback_hosts: Vec<Host>) -> Self {
+        self.fallback_hosts = fallback_hosts;
+        self
+    }
+
     /// Sets the optional authentication parameters.
     pub fn with_auth(mut self, auth: Option<Auth>) -> Self {
         self.auth = auth;
@@ -157,6 +366,65 @@ impl ConfigBuilder {
         self
     }
 
+    /// Sets the local address to bind to before connecting, e.g. to choose the outgoing
+    /// interface of a multi-homed host. Use port `0` to let the system pick a port.
+    ///
+    /// This is ignored by non-network connections, and when connecting over an
+    /// existing stream (see [`crate::blocking::Connection::from_stream`]).
+    pub fn with_bind_address(mut self, bind_address: SocketAddr) -> Self {
+        self.bind_address = Some(bind_address);
+        self
+    }
+
+    /// Sets whether to disable Nagle's algorithm on the TCP socket (`TCP_NODELAY`).
+    ///
+    /// Defaults to `true`, since NUT exchanges small requests and responses, which Nagle's
+    /// algorithm would delay. This is ignored when connecting over an existing stream.
+    pub fn with_tcp_nodelay(mut self, tcp_nodelay: bool) -> Self {
+        self.tcp_nodelay = Some(tcp_nodelay);
+        self
+    }
+
+    /// Sets the IP address family to connect with, e.g. to force IPv4 on a dual-stack host.
+    ///
+    /// Defaults to [`AddressFamily::Any`], which uses the first address the host resolves to.
+    /// Connecting fails if the host has no address in the requested family.
+    pub fn with_address_family(mut self, address_family: AddressFamily) -> Self {
+        self.address_family = Some(address_family);
+        self
+    }
+
+    /// Sets the maximum time to wait for data from the server, for each read.
+    ///
+    /// A read that takes longer fails with [`NutError::Timeout`](crate::NutError::Timeout).
+    /// By default, reads wait indefinitely. This is only enforced by the blocking client,
+    /// and ignored for custom streams.
+    pub fn with_read_timeout(mut self, read_timeout: Duration) -> Self {
+        self.read_timeout = Some(read_timeout);
+        self
+    }
+
+    /// Sets the maximum time to wait for the server to accept data, for each write
+    /// (e.g. if its receive buffer is full).
+    ///
+    /// A write that takes longer fails with [`NutError::Timeout`](crate::NutError::Timeout).
+    /// By default, writes wait indefinitely. This is only enforced by the blocking client,
+    /// and ignored for custom streams.
+    pub fn with_write_timeout(mut self, write_timeout: Duration) -> Self {
+        self.write_timeout = Some(write_timeout);
+        self
+    }
+
+    /// Sets a deadline for the whole connection: connecting, logging in, and every request
+    /// made until the deadline is changed (see `Connection::set_deadline`).
+    ///
+    /// Once it has passed, operations fail with [`NutError::Timeout`](crate::NutError::Timeout).
+    /// This is only enforced by the blocking client.
+    pub fn with_deadline(mut self, deadline: Instant) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
     /// Enables SSL on the connection.
     ///
     /// This will enable strict SSL verification (including hostname),
@@ -176,21 +444,219 @@ impl ConfigBuilder {
         self
     }
 
+    /// Pins the SHA-256 fingerprint of the server certificate.
+    ///
+    /// The certificate authority and hostname are not verified, but the connection is
+    /// rejected unless the leaf certificate presented by the server matches this fingerprint.
+    /// This is useful for servers with self-signed certificates.
+    ///
+    /// Note: you must still use `.with_ssl(true)` to turn on SSL.
+    #[cfg(feature = "ssl")]
+    pub fn with_pinned_cert_sha256(mut self, fingerprint: [u8; 32]) -> Self {
+        self.ssl_pinned_cert = Some(fingerprint);
+        self
+    }
+
+    /// Sets the rustls configuration to use for SSL, instead of the one built by this crate
+    /// (e.g. for custom certificate authorities, verifiers or cipher suites).
+    ///
+    /// The certificate is then verified by the given configuration only: `.with_insecure_ssl`
+    /// and `.with_pinned_cert_sha256` are ignored. The hostname of the host is still given to
+    /// the verifier, so it must be a DNS name (see [`Host::tcp_with_hostname`]).
+    ///
+    /// Note: you must still use `.with_ssl(true)` to turn on SSL.
+    #[cfg(feature = "ssl")]
+    pub fn with_rustls_config(mut self, config: std::sync::Arc<rustls::ClientConfig>) -> Self {
+        self.ssl_client_config = Some(crate::ssl::CustomClientConfig(config));
+        self
+    }
+
     /// Enables debugging network calls by printing to stderr.
     pub fn with_debug(mut self, debug: bool) -> Self {
         self.debug = Some(debug);
         self
     }
 
+    /// Sets whether to send the username and password (if any) when connecting.
+    ///
+    /// Defaults to `true`. When disabled, the connection is left unauthenticated, and
+    /// `set_username`/`set_password` can be called manually at the appropriate time.
+    pub fn with_auto_login(mut self, auto_login: bool) -> Self {
+        self.auto_login = Some(auto_login);
+        self
+    }
+
+    /// Sets whether to log in to a UPS device (`LOGIN`) and retry once, when `get_var` or
+    /// `list_vars` is denied access to it.
+    ///
+    /// Defaults to `false`, since logging in counts the client as using the UPS for power
+    /// (see `get_num_logins`). Only applies when a username and password are configured.
+    /// If `LOGIN` fails, its error is returned rather than the denied access.
+    pub fn with_auto_login_on_access_denied(mut self, login_on_access_denied: bool) -> Self {
+        self.login_on_access_denied = Some(login_on_access_denied);
+        self
+    }
+
+    /// Sets whether to refuse every command that changes the state of a UPS device
+    /// (`SET`, `INSTCMD` and `FSD`), including raw command lines.
+    ///
+    /// Defaults to `false`. When enabled, such commands fail with
+    /// [`NutError::ReadOnly`](crate::NutError::ReadOnly) without being sent.
+    pub fn with_read_only(mut self, read_only: bool) -> Self {
+        self.read_only = Some(read_only);
+        self
+    }
+
+    /// Sets whether to query the network protocol version (`NETVER`) and the server version
+    /// (`VER`) when connecting, after the TLS handshake and login (if any).
+    ///
+    /// Defaults to `true`. Disable it for servers that reject queries at that stage;
+    /// the versions are then not available from `Connection::network_version` and
+    /// `Connection::server_version`.
+    pub fn with_version_probe(mut self, probe_version: bool) -> Self {
+        self.probe_version = Some(probe_version);
+        self
+    }
+
     /// Builds the configuration with this builder.
     pub fn build(self) -> Config {
-        Config::new(
+        let mut config = Config::new(
             self.host.unwrap_or_default(),
             self.auth,
             self.timeout.unwrap_or_else(|| Duration::from_secs(5)),
             self.ssl.unwrap_or(false),
             self.ssl_insecure.unwrap_or(false),
             self.debug.unwrap_or(false),
-        )
+        );
+        config.auto_login = self.auto_login.unwrap_or(true);
+        config.login_on_access_denied = self.login_on_access_denied.unwrap_or(false);
+        config.read_only = self.read_only.unwrap_or(false);
+        config.ssl_pinned_cert = self.ssl_pinned_cert;
+        config.bind_address = self.bind_address;
+        config.tcp_nodelay = self.tcp_nodelay.unwrap_or(true);
+        config.probe_version = self.probe_version.unwrap_or(true);
+        config.deadline = self.deadline;
+        config.read_timeout = self.read_timeout;
+        config.write_timeout = self.write_timeout;
+        config.address_family = self.address_family.unwrap_or_default();
+        config.fallback_hosts = self.fallback_hosts;
+        #[cfg(feature = "ssl")]
+        {
+            config.ssl_client_config = self.ssl_client_config;
+        }
+        config
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builder_from_vars() {
+        let vars = |key: &str| match key {
+            "NUT_HOST" => Some(String::from("127.0.0.1")),
+            "NUT_PORT" => Some(String::from("1234")),
+            "NUT_USER" => Some(String::from("monuser")),
+            "NUT_SSL" => Some(String::from("yes")),
+            _ => None,
+        };
+        let config = ConfigBuilder::from_vars(vars).unwrap().build();
+        let Host::Tcp(host) = &config.host;
+        assert_eq!(host.addr, "127.0.0.1:1234".parse().unwrap());
+        let auth = config.auth.unwrap();
+        assert_eq!(auth.username, "monuser");
+        assert_eq!(auth.password, None);
+        assert!(config.ssl);
+        assert!(!config.ssl_insecure);
+
+        let config = ConfigBuilder::from_vars(|_| None).unwrap().build();
+        assert_eq!(config.host.hostname().as_deref(), Some("localhost"));
+        assert!(config.auth.is_none());
+        assert!(!config.ssl);
+
+        let invalid_port = |key: &str| (key == "NUT_PORT").then(|| String::from("http"));
+        assert!(ConfigBuilder::from_vars(invalid_port).is_err());
+        let invalid_ssl = |key: &str| (key == "NUT_SSL").then(|| String::from("maybe"));
+        assert!(ConfigBuilder::from_vars(invalid_ssl).is_err());
+    }
+
+    #[test]
+    fn test_host_with_hostname() {
+        let addr: SocketAddr = "192.0.2.10:3493".parse().unwrap();
+        let host = Host::tcp_with_hostname(addr, "ups.example.com");
+        assert_eq!(host.hostname().as_deref(), Some("ups.example.com"));
+        let Host::Tcp(host) = host;
+        assert_eq!(host.addr, addr);
+
+        assert_eq!(Host::from(addr).hostname().as_deref(), Some("192.0.2.10"));
+    }
+
+    #[test]
+    fn test_config_display() {
+        let config = ConfigBuilder::new()
+            .with_host(Host::try_from((String::from("localhost"), 3493)).unwrap())
+            .with_auth(Some(Auth::new("admin".into(), Some("secret".into()))))
+            .build();
+        let display = config.to_string();
+        assert!(display.starts_with("localhost ("));
+        assert!(display.ends_with(":3493), user admin, timeout 5s"));
+
+        let mut config = ConfigBuilder::new()
+            .with_host("127.0.0.1:3493".parse::<SocketAddr>().unwrap().into())
+            .with_timeout(Duration::from_millis(1500))
+            .build();
+        config.ssl = true;
+        assert_eq!(
+            config.to_string(),
+            "127.0.0.1:3493, no login, SSL, timeout 1.5s"
+        );
+
+        // The brackets of an IPv6 address are not part of the hostname
+        let config = ConfigBuilder::new()
+            .with_host(Host::try_from((String::from("[::1]"), 3493)).unwrap())
+            .build();
+        assert_eq!(config.to_string(), "[::1]:3493, no login, timeout 5s");
+    }
+
+    #[test]
+    fn test_address_family() {
+        let Host::Tcp(host) = Host::try_from((String::from("127.0.0.1"), 3493)).unwrap();
+        assert_eq!(
+            host.resolve(AddressFamily::Any).unwrap(),
+            "127.0.0.1:3493".parse().unwrap()
+        );
+        assert_eq!(
+            host.resolve(AddressFamily::V4).unwrap(),
+            "127.0.0.1:3493".parse().unwrap()
+        );
+        assert!(host.resolve(AddressFamily::V6).is_err());
+
+        let Host::Tcp(host) = Host::try_from((String::from("[::1]"), 3493)).unwrap();
+        assert!(host.resolve(AddressFamily::V6).unwrap().is_ipv6());
+        assert!(host.resolve(AddressFamily::V4).is_err());
+    }
+
+    #[test]
+    fn test_host_ipv6() {
+        let host = Host::try_from((String::from("[::1]"), 3493)).unwrap();
+        let Host::Tcp(host) = host;
+        assert_eq!(host.addr, "[::1]:3493".parse().unwrap());
+        assert_eq!(host.hostname, "[::1]");
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_host_ipv6_scoped() {
+        let host = Host::try_from((String::from("fe80::1%lo"), 3493)).unwrap();
+        let Host::Tcp(host) = host;
+        match host.addr {
+            SocketAddr::V6(addr) => {
+                assert_eq!(addr.ip(), &"fe80::1".parse::<std::net::Ipv6Addr>().unwrap());
+                assert_ne!(addr.scope_id(), 0);
+                assert_eq!(addr.port(), 3493);
+            }
+            SocketAddr::V4(_) => panic!("expected an IPv6 address"),
+        }
     }
 }
@@ -19,7 +19,8 @@ pub enum NutError {
     InstCmdFailed,
     /// Server failed to deliver the set request to the driver.
     SetFailed,
-    /// The requested variable in a SET command is not writable.
+    /// The requested variable in a SET command is not writable, or the client is read-only
+    /// (see [`ConfigBuilder::with_read_only`](crate::ConfigBuilder::with_read_only)).
     ReadOnly,
     /// The requested value in a SET command is too long.
     TooLong,
@@ -60,8 +61,18 @@ pub enum NutError {
     SslNotSupported,
     /// Occurs when trying to initialize a strict SSL connection with an invalid hostname.
     SslInvalidHostname,
+    /// Occurs when the server accepted to start SSL, but the TLS handshake failed.
+    SslHandshakeFailed(String),
     /// Occurs when the client used a feature that is disabled by the server.
     FeatureNotConfigured,
+    /// Occurs when a previous request was cancelled before its response was fully read,
+    /// leaving the connection in an unknown state. A new connection must be established.
+    Poisoned,
+    /// Occurs when the deadline of the connection has passed.
+    Timeout,
+    /// Occurs when the value of a variable can't be parsed into the requested type
+    /// (see [`Variable::parse_value`](crate::Variable::parse_value)).
+    ParseValue(String),
     /// Generic (usually internal) client error.
     Generic(String),
 }
@@ -94,45 +105,60 @@ impl fmt::Display for NutError {
             Self::UnexpectedResponse => write!(f, "Unexpected server response content"),
             Self::UnknownResponseType(ty) => write!(f, "Unknown response type: {}", ty),
             Self::SslNotSupported => write!(f, "SSL not supported by server or transport"),
+            Self::SslHandshakeFailed(reason) => write!(f, "SSL handshake failed: {}", reason),
             Self::SslInvalidHostname => write!(
                 f,
-                "Given hostname cannot be used for a strict SSL connection"
+                "Given hostname cannot be used for a strict SSL connection \
+                (IP addresses cannot be verified, see Host::tcp_with_hostname)"
             ),
             Self::FeatureNotConfigured => write!(f, "Feature not configured by server"),
+            Self::Poisoned => write!(
+                f,
+                "Connection is unusable: a previous request was cancelled mid-response"
+            ),
+            Self::Timeout => write!(f, "Operation timed out"),
+            Self::ParseValue(msg) => write!(f, "Failed to parse value: {}", msg),
             Self::Generic(msg) => write!(f, "Client error: {}", msg),
         }
     }
 }
 
+impl NutError {
+    /// Maps an error code from an `ERR <code>` response, if it is known.
+    pub(crate) fn from_code(code: &str) -> Option<Self> {
+        match code {
+            "ACCESS-DENIED" => Some(Self::AccessDenied),
+            "UNKNOWN-UPS" => Some(Self::UnknownUps),
+            "VAR-NOT-SUPPORTED" => Some(Self::VarNotSupported),
+            "CMD-NOT-SUPPORTED" => Some(Self::CmdNotSupported),
+            "INVALID-ARGUMENT" => Some(Self::InvalidArgument),
+            "INSTCMD-FAILED" => Some(Self::InstCmdFailed),
+            "SET-FAILED" => Some(Self::SetFailed),
+            "READONLY" => Some(Self::ReadOnly),
+            "TOO-LONG" => Some(Self::TooLong),
+            "FEATURE-NOT-SUPPORTED" => Some(Self::FeatureNotSupported),
+            "FEATURE-NOT-CONFIGURED" => Some(Self::FeatureNotConfigured),
+            "ALREADY-SSL-MODE" => Some(Self::AlreadySslMode),
+            "DRIVER-NOT-CONNECTED" => Some(Self::DriverNotConnected),
+            "DATA-STALE" => Some(Self::DataStale),
+            "ALREADY-LOGGED-IN" => Some(Self::AlreadyLoggedIn),
+            "INVALID-PASSWORD" => Some(Self::InvalidPassword),
+            "ALREADY-SET-PASSWORD" => Some(Self::AlreadySetPassword),
+            "INVALID-USERNAME" => Some(Self::InvalidUsername),
+            "ALREADY-SET-USERNAME" => Some(Self::AlreadySetUsername),
+            "USERNAME-REQUIRED" => Some(Self::UsernameRequired),
+            "PASSWORD-REQUIRED" => Some(Self::PasswordRequired),
+            "UNKNOWN-COMMAND" => Some(Self::UnknownCommand),
+            "INVALID-VALUE" => Some(Self::InvalidValue),
+            _ => None,
+        }
+    }
+}
+
 impl<T: AsRef<ClientSentences>> From<T> for NutError {
     fn from(sentence: T) -> Self {
         if let ClientSentences::RespondErr { message, .. } = sentence.as_ref() {
-            match message.as_str() {
-                "ACCESS-DENIED" => Self::AccessDenied,
-                "UNKNOWN-UPS" => Self::UnknownUps,
-                "VAR-NOT-SUPPORTED" => Self::VarNotSupported,
-                "CMD-NOT-SUPPORTED" => Self::CmdNotSupported,
-                "INVALID-ARGUMENT" => Self::InvalidArgument,
-                "INSTCMD-FAILED" => Self::InstCmdFailed,
-                "SET-FAILED" => Self::SetFailed,
-                "READONLY" => Self::ReadOnly,
-                "TOO-LONG" => Self::TooLong,
-                "FEATURE-NOT-SUPPORTED" => Self::FeatureNotSupported,
-                "FEATURE-NOT-CONFIGURED" => Self::FeatureNotConfigured,
-                "ALREADY-SSL-MODE" => Self::AlreadySslMode,
-                "DRIVER-NOT-CONNECTED" => Self::DriverNotConnected,
-                "DATA-STALE" => Self::DataStale,
-                "ALREADY-LOGGED-IN" => Self::AlreadyLoggedIn,
-                "INVALID-PASSWORD" => Self::InvalidPassword,
-                "ALREADY-SET-PASSWORD" => Self::AlreadySetPassword,
-                "INVALID-USERNAME" => Self::InvalidUsername,
-                "ALREADY-SET-USERNAME" => Self::AlreadySetUsername,
-                "USERNAME-REQUIRED" => Self::UsernameRequired,
-                "PASSWORD-REQUIRED" => Self::PasswordRequired,
-                "UNKNOWN-COMMAND" => Self::UnknownCommand,
-                "INVALID-VALUE" => Self::InvalidValue,
-                _ => Self::Generic(message.to_string()),
-            }
+            Self::from_code(message).unwrap_or_else(|| Self::Generic(message.to_string()))
         } else {
             // This is not supposed to happen...
             panic!("Cannot convert {:?} into NutError", sentence.as_ref());
@@ -0,0 +1,2 @@
+/// Prometheus text-format exporter.
+pub mod prometheus;
@@ -0,0 +1,97 @@
+use std::collections::BTreeMap;
+
+use crate::blocking::Connection;
+use crate::Variable;
+
+/// Renders the numeric variables of every UPS device in the Prometheus text format.
+///
+/// Each variable is emitted as a gauge, `nut_<name>{ups="<ups_name>"} <value>`, where the
+/// variable name is sanitized (e.g. `battery.charge` becomes `nut_battery_charge`).
+/// Non-numeric variables are skipped.
+pub fn render(conn: &mut Connection) -> crate::Result<String> {
+    let vars = conn
+        .iter_all_vars()
+        .collect::<crate::Result<Vec<(String, Variable)>>>()?;
+    Ok(render_vars(
+        vars.iter().map(|(ups_name, var)| (ups_name.as_str(), var)),
+    ))
+}
+
+/// Renders `(ups_name, variable)` pairs, grouping the samples of each metric together.
+fn render_vars<'a, I>(vars: I) -> String
+where
+    I: IntoIterator<Item = (&'a str, &'a Variable)>,
+{
+    let mut metrics: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for (ups_name, var) in vars {
+        if let Some(value) = var.as_f64() {
+            let name = metric_name(var.name());
+            let sample = format!(
+                "{}{{ups=\"{}\"}} {}",
+                name,
+                escape_label_value(ups_name),
+                value
+            );
+            metrics.entry(name).or_default().push(sample);
+        }
+    }
+
+    let mut output = String::new();
+    for (name, samples) in metrics {
+        output.push_str(&format!("# TYPE {} gauge\n", name));
+        for sample in samples {
+            output.push_str(&sample);
+            output.push('\n');
+        }
+    }
+    output
+}
+
+/// Converts a NUT variable name into a valid Prometheus metric name.
+fn metric_name(var_name: &str) -> String {
+    let sanitized: String = var_name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    format!("nut_{}", sanitized)
+}
+
+/// Escapes a label value (backslash, double-quote and line feed).
+fn escape_label_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_vars() {
+        let vars = [
+            ("ups0", Variable::parse("battery.charge", "100".into())),
+            ("ups0", Variable::parse("device.model", "Smart-UPS".into())),
+            ("ups0", Variable::parse("ups.load", "13.5".into())),
+            ("ups\"1", Variable::parse("battery.charge", "87".into())),
+        ];
+        assert_eq!(
+            render_vars(vars.iter().map(|(ups_name, var)| (*ups_name, var))),
+            "# TYPE nut_battery_charge gauge\n\
+             nut_battery_charge{ups=\"ups0\"} 100\n\
+             nut_battery_charge{ups=\"ups\\\"1\"} 87\n\
+             # TYPE nut_ups_load gauge\n\
+             nut_ups_load{ups=\"ups0\"} 13.5\n"
+        );
+    }
+
+    #[test]
+    fn test_metric_name() {
+        assert_eq!(metric_name("battery.charge"), "nut_battery_charge");
+        assert_eq!(
+            metric_name("outlet.1.realpower-max"),
+            "nut_outlet_1_realpower_max"
+        );
+    }
+}
@@ -5,19 +5,28 @@
 //! The `rups` crate provides a network client implementation
 //! for Network UPS Tools (NUT) servers.
 
+pub use blocking::SharedConnection;
+pub use cmd::OwnedCommand;
 pub use config::*;
 pub use error::*;
+#[cfg(feature = "ssl")]
+pub use ssl::TlsInfo;
 pub use util::*;
 pub use var::*;
 
 /// Blocking client implementation for NUT.
 pub mod blocking;
+/// Exporters of UPS data into other formats.
+#[cfg(feature = "prometheus")]
+pub mod export;
 /// NUT protocol implementation (v1.2).
 ///
 /// Reference: <https://networkupstools.org/docs/developer-guide.chunked/ar01s09.html>
 #[allow(dead_code)]
 #[macro_use]
 pub(crate) mod proto;
+/// Clients that reconnect transparently when the connection is lost.
+pub mod resilient;
 /// Async client implementation for NUT, using Tokio.
 #[cfg(feature = "async")]
 pub mod tokio;
@@ -27,5 +36,7 @@ mod config;
 mod error;
 #[cfg(feature = "ssl")]
 mod ssl;
+#[cfg(test)]
+pub(crate) mod test_util;
 mod util;
 mod var;
@@ -454,6 +454,18 @@ impl_sentences! {
             3: ups_name,
         }
     ),
+    /// A successful response followed by informational tokens, as sent by some servers.
+    /// Only matched when no other `OK` sentence does.
+    GenericOkWithInfo (
+        {
+            0: Ok,
+        },
+        {},
+        {
+            /// The tokens following `OK`.
+            1...: info
+        }
+    ),
 }
 
 #[cfg(test)]
@@ -468,6 +480,12 @@ mod tests {
             ["OK"] <=>
             Sentences::GenericOk {}
         );
+        test_encode_decode!(
+            ["OK", "extra"] <=>
+            Sentences::GenericOkWithInfo {
+                info: vec!["extra".into()],
+            }
+        );
         test_encode_decode!(
             ["OK", "FSD-SET"] <=>
             Sentences::FsdOk {}
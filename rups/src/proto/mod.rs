@@ -74,6 +74,7 @@ macro_rules! impl_words {
             }
 
             /// Whether the `Word` matches another.
+            /// An unrecognized word (`None`) is only matched by `Arg`.
             pub(crate) fn matches(&self, other: Option<&Option<Self>>) -> bool {
                 if let Some(other) = other {
                     if self == &Word::Arg {
@@ -81,7 +82,7 @@ macro_rules! impl_words {
                     } else if let Some(other) = other {
                         self == other
                     } else {
-                        self == &Word::EOL
+                        false
                     }
                 } else {
                     false
@@ -147,6 +148,12 @@ macro_rules! impl_sentences {
             )*
         }
 
+        impl AsRef<Sentences> for Sentences {
+            fn as_ref(&self) -> &Sentences {
+                self
+            }
+        }
+
         impl Sentences {
             /// Decodes a sentence. Returns `None` if the pattern cannot be recognized.
             pub(crate) fn decode(raw: Vec<String>) -> Option<Sentences> {
@@ -288,6 +295,8 @@ impl_words! {
     Ok("OK"),
     /// Client setting password.
     Password("PASSWORD"),
+    /// Client verifying it has primary-level access to the UPS device (NUT 2.8+ name of `MASTER`).
+    Primary("PRIMARY"),
     /// Represents a range of numerical values.
     Range("RANGE"),
     /// Represents a mutable variable.
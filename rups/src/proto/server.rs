@@ -91,6 +91,15 @@ impl_sentences! {
             3: cmd_name,
         }
     ),
+    /// Client requests the list of UPS devices.
+    QueryListUps (
+        {
+            0: List,
+            1: Ups,
+            2: EOL,
+        },
+        {}
+    ),
     /// Client requests the list of variables for the given `ups_name` device.
     QueryListVar (
         {
@@ -243,6 +252,18 @@ impl_sentences! {
             1: ups_name,
         }
     ),
+    /// Client asserts primary-level access to the `ups_name` device (NUT 2.8+ name of `MASTER`).
+    ExecPrimary (
+        {
+            0: Primary,
+            1: Arg,
+            2: EOL,
+        },
+        {
+            /// The name of the UPS device.
+            1: ups_name,
+        }
+    ),
     /// Client requests the forced shut-down of the `ups_name` device.
     ExecForcedShutDown (
         {
@@ -371,6 +392,10 @@ mod tests {
                 ups_name: "nutdev".into(),
             }
         );
+        test_encode_decode!(
+            ["LIST", "UPS"] <=>
+            Sentences::QueryListUps {}
+        );
         test_encode_decode!(
             ["LIST", "RW", "nutdev"] <=>
             Sentences::QueryListRw {
@@ -434,6 +459,12 @@ mod tests {
                 ups_name: "nutdev".into(),
             }
         );
+        test_encode_decode!(
+            ["PRIMARY", "nutdev"] <=>
+            Sentences::ExecPrimary {
+                ups_name: "nutdev".into(),
+            }
+        );
         test_encode_decode!(
             ["FSD", "nutdev"] <=>
             Sentences::ExecForcedShutDown {
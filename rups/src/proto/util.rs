@@ -1,24 +1,105 @@
 /// Splits a sentence (line) into a `Vec<String>`, minding quotation marks
 /// for words with spaces in them.
 ///
+/// This follows upsd's quoting rules rather than shell rules: only double quotes
+/// group words, and a backslash escapes the following character (e.g. `\"`).
+/// Single quotes have no special meaning.
+///
 /// Returns `None` if the sentence cannot be split safely (usually unbalanced quotation marks).
 pub fn split_sentence<T: AsRef<str>>(sentence: T) -> Option<Vec<String>> {
-    shell_words::split(sentence.as_ref()).ok()
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut in_quotes = false;
+    let mut chars = sentence.as_ref().chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                word.push(chars.next()?);
+                in_word = true;
+            }
+            '"' => {
+                in_quotes = !in_quotes;
+                in_word = true;
+            }
+            c if c.is_whitespace() && !in_quotes => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            c => {
+                word.push(c);
+                in_word = true;
+            }
+        }
+    }
+
+    if in_quotes {
+        return None;
+    }
+    if in_word {
+        words.push(word);
+    }
+    Some(words)
 }
 
 /// Joins a collection of words (`&str`) into one sentence string,
 /// adding quotation marks for words with spaces in them.
+///
+/// This is the inverse of [`split_sentence`]: double quotes and backslashes are escaped
+/// with a backslash, and empty words are written as `""`.
 pub fn join_sentence<I, S>(words: I) -> String
 where
     I: IntoIterator<Item = S>,
     S: AsRef<str>,
 {
-    shell_words::join(words)
+    let mut sentence = String::new();
+    for (i, word) in words.into_iter().enumerate() {
+        let word = word.as_ref();
+        if i > 0 {
+            sentence.push(' ');
+        }
+        let quoted = word.is_empty() || word.chars().any(char::is_whitespace);
+        if quoted {
+            sentence.push('"');
+        }
+        for c in word.chars() {
+            if c == '"' || c == '\\' {
+                sentence.push('\\');
+            }
+            sentence.push(c);
+        }
+        if quoted {
+            sentence.push('"');
+        }
+    }
+    sentence
+}
+
+/// Whether a command line changes the state of a UPS device (`SET`, `INSTCMD` or `FSD`).
+pub fn is_mutating(line: &str) -> bool {
+    let command = line.split_whitespace().next().unwrap_or_default();
+    ["SET", "INSTCMD", "FSD"]
+        .iter()
+        .any(|mutating| command.eq_ignore_ascii_case(mutating))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+
+    #[test]
+    fn test_is_mutating() {
+        assert!(is_mutating("SET VAR nutdev ups.id \"Rack 2\""));
+        assert!(is_mutating("INSTCMD nutdev beeper.disable"));
+        assert!(is_mutating("fsd nutdev"));
+        assert!(!is_mutating("GET VAR nutdev ups.status"));
+        assert!(!is_mutating("SETTINGS"));
+        assert!(!is_mutating(""));
+    }
+
     #[test]
     fn test_split() {
         assert_eq!(
@@ -32,10 +113,66 @@ mod tests {
         assert_eq!(split_sentence("\"AbC dEf GHi"), None);
     }
 
+    #[test]
+    fn test_split_embedded_quotes() {
+        assert_eq!(
+            split_sentence(r#"DESC nutdev test.var "He said \"hi\"""#),
+            Some(vec![
+                "DESC".into(),
+                "nutdev".into(),
+                "test.var".into(),
+                "He said \"hi\"".into()
+            ])
+        );
+        assert_eq!(
+            split_sentence(r#"UPSDESC nutdev "Bob's \\ UPS""#),
+            Some(vec![
+                "UPSDESC".into(),
+                "nutdev".into(),
+                "Bob's \\ UPS".into()
+            ])
+        );
+        assert_eq!(
+            split_sentence("UPSDESC nutdev Bob's"),
+            Some(vec!["UPSDESC".into(), "nutdev".into(), "Bob's".into()])
+        );
+        assert_eq!(
+            split_sentence(r#"VAR nutdev test.var """#),
+            Some(vec![
+                "VAR".into(),
+                "nutdev".into(),
+                "test.var".into(),
+                "".into()
+            ])
+        );
+        assert_eq!(split_sentence(r#"VAR nutdev test.var \"#), None);
+    }
+
     #[test]
     fn test_join() {
         assert_eq!(join_sentence(vec!["AbC", "dEf", "GHi"]), "AbC dEf GHi",);
-        assert_eq!(join_sentence(vec!["AbC dEf", "GHi"]), "'AbC dEf' GHi",);
-        assert_eq!(join_sentence(vec!["\"AbC dEf", "GHi"]), "'\"AbC dEf' GHi",);
+        assert_eq!(join_sentence(vec!["AbC dEf", "GHi"]), "\"AbC dEf\" GHi",);
+        assert_eq!(
+            join_sentence(vec!["\"AbC dEf", "GHi"]),
+            "\"\\\"AbC dEf\" GHi",
+        );
+        assert_eq!(join_sentence(vec!["Bob's", ""]), "Bob's \"\"");
+        assert_eq!(join_sentence(vec!["C:\\UPS"]), "C:\\\\UPS");
+    }
+
+    #[test]
+    fn test_join_split_roundtrip() {
+        let words = vec![
+            "SET",
+            "VAR",
+            "nutdev",
+            "ups.id",
+            "He said \"hi\" \\ 'bye'",
+            "",
+        ];
+        assert_eq!(
+            split_sentence(join_sentence(&words)),
+            Some(words.into_iter().map(String::from).collect())
+        );
     }
 }
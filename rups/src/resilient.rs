@@ -0,0 +1,296 @@
+use crate::{ClientError, Config, NutError};
+
+/// Whether an error means that the connection is lost (or can no longer be used), so that
+/// reconnecting may help.
+fn is_disconnected(err: &ClientError) -> bool {
+    match err {
+        ClientError::Io(e) => !matches!(
+            e.kind(),
+            std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+        ),
+        ClientError::Nut(NutError::Poisoned) => true,
+        ClientError::Nut(_) => false,
+    }
+}
+
+/// Whether an error is a timeout, after which the response may still arrive late and be
+/// read as the answer to the next request.
+fn is_timeout(err: &ClientError) -> bool {
+    match err {
+        ClientError::Io(e) => matches!(
+            e.kind(),
+            std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+        ),
+        ClientError::Nut(NutError::Timeout) => true,
+        ClientError::Nut(_) => false,
+    }
+}
+
+/// A macro for implementing the methods of the reconnecting connections, which forward
+/// to the underlying connection and retry once after reconnecting.
+macro_rules! implement_reconnecting_methods {
+    (
+        $(
+            $(#[$attr:meta])+
+            fn $name:ident($($argname:ident: $argty:ty),*) -> $retty:ty;
+        )*
+    ) => {
+        impl ReconnectingConnection {
+            $(
+                $(#[$attr])*
+                pub fn $name(&mut self$(, $argname: $argty)*) -> crate::Result<$retty> {
+                    self.call(|conn| conn.$name($($argname),*))
+                }
+            )*
+        }
+
+        #[cfg(feature = "async")]
+        impl AsyncReconnectingConnection {
+            $(
+                $(#[$attr])*
+                pub async fn $name(&mut self$(, $argname: $argty)*) -> crate::Result<$retty> {
+                    let result = self.connection().await?.$name($($argname),*).await;
+                    match result {
+                        Err(e) if is_timeout(&e) => {
+                            if self.config.debug {
+                                eprintln!("DEBUG <- (!) Request timed out ({}), disconnecting", e);
+                            }
+                            self.conn = None;
+                            Err(e)
+                        }
+                        Err(e) if is_disconnected(&e) => {
+                            if self.config.debug {
+                                eprintln!("DEBUG <- (!) Connection lost ({}), reconnecting", e);
+                            }
+                            self.conn = None;
+                            self.connection().await?.$name($($argname),*).await
+                        }
+                        result => result,
+                    }
+                }
+            )*
+        }
+    };
+}
+
+/// A blocking NUT client that connects lazily, and transparently reconnects when the
+/// connection is lost.
+///
+/// A request that fails because the connection was lost (an I/O error, such as the server
+/// closing it) is retried once on a new connection. Errors from the server are returned
+/// as-is. Timeouts are returned without retrying, but the connection is dropped, since the
+/// late response would otherwise be read as the answer to the next request.
+///
+/// Note that requests are retried blindly: only use this for queries, not for commands
+/// that must not run twice.
+pub struct ReconnectingConnection {
+    config: Config,
+    conn: Option<crate::blocking::Connection>,
+}
+
+impl ReconnectingConnection {
+    /// Creates the client, without connecting yet.
+    pub fn new(config: Config) -> Self {
+        ReconnectingConnection { config, conn: None }
+    }
+
+    /// Returns the current connection, connecting first if needed.
+    pub fn connection(&mut self) -> crate::Result<&mut crate::blocking::Connection> {
+        if self.conn.is_none() {
+            self.conn = Some(crate::blocking::Connection::new(&self.config)?);
+        }
+        Ok(self.conn.as_mut().unwrap())
+    }
+
+    /// Runs a request on the connection, reconnecting and retrying it once if the
+    /// connection was lost, or dropping the connection if the request timed out.
+    pub fn call<T, F>(&mut self, mut request: F) -> crate::Result<T>
+    where
+        F: FnMut(&mut crate::blocking::Connection) -> crate::Result<T>,
+    {
+        match request(self.connection()?) {
+            Err(e) if is_timeout(&e) => {
+                if self.config.debug {
+                    eprintln!("DEBUG <- (!) Request timed out ({}), disconnecting", e);
+                }
+                self.conn = None;
+                Err(e)
+            }
+            Err(e) if is_disconnected(&e) => {
+                if self.config.debug {
+                    eprintln!("DEBUG <- (!) Connection lost ({}), reconnecting", e);
+                }
+                self.conn = None;
+                request(self.connection()?)
+            }
+            result => result,
+        }
+    }
+}
+
+/// An async NUT client that connects lazily, and transparently reconnects when the
+/// connection is lost.
+///
+/// See [`ReconnectingConnection`] for the blocking version, and the reconnection rules.
+#[cfg(feature = "async")]
+pub struct AsyncReconnectingConnection {
+    config: Config,
+    conn: Option<crate::tokio::Connection>,
+}
+
+#[cfg(feature = "async")]
+impl AsyncReconnectingConnection {
+    /// Creates the client, without connecting yet.
+    pub fn new(config: Config) -> Self {
+        AsyncReconnectingConnection { config, conn: None }
+    }
+
+    /// Returns the current connection, connecting first if needed.
+    pub async fn connection(&mut self) -> crate::Result<&mut crate::tokio::Connection> {
+        if self.conn.is_none() {
+            self.conn = Some(crate::tokio::Connection::new(&self.config).await?);
+        }
+        Ok(self.conn.as_mut().unwrap())
+    }
+}
+
+implement_reconnecting_methods! {
+    /// Queries a list of UPS devices.
+    fn list_ups() -> Vec<(String, String)>;
+
+    /// Queries the list of variables for a UPS device.
+    fn list_vars(ups_name: &str) -> Vec<crate::Variable>;
+
+    /// Queries one variable for a UPS device.
+    fn get_var(ups_name: &str, variable: &str) -> crate::Variable;
+
+    /// Queries one variable for a UPS device, returning `None` if the device doesn't
+    /// support it.
+    fn try_get_var(ups_name: &str, variable: &str) -> Option<crate::Variable>;
+
+    /// Queries the status flags of a UPS device (`ups.status`).
+    fn get_ups_status(ups_name: &str) -> std::collections::HashSet<crate::UpsStatusFlag>;
+
+    /// Queries the [`CommonMetrics`](crate::CommonMetrics) of a UPS device.
+    fn common_metrics(ups_name: &str) -> crate::CommonMetrics;
+
+    /// Queries the number of logins to the specified UPS.
+    fn get_num_logins(ups_name: &str) -> i32;
+
+    /// Queries the list of client IP addresses connected to the given device.
+    fn list_clients(ups_name: &str) -> Vec<String>;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::{TcpListener, TcpStream};
+
+    use super::*;
+    use crate::ConfigBuilder;
+
+    /// Answers each request line read from the stream with the next response, and returns
+    /// the requests.
+    fn answer(stream: &mut TcpStream, responses: &[&str]) -> Vec<String> {
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut lines = Vec::new();
+        for response in responses {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            lines.push(line);
+            writeln!(stream, "{}", response).unwrap();
+        }
+        lines
+    }
+
+    #[test]
+    fn test_reconnect_after_disconnect() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let config = ConfigBuilder::new()
+            .with_host(listener.local_addr().unwrap().into())
+            .build();
+        let server = std::thread::spawn(move || {
+            // The first connection is closed after the version probes
+            let (mut stream, _) = listener.accept().unwrap();
+            answer(&mut stream, &["1.2", "Network UPS Tools upsd 2.8.0"]);
+            drop(stream);
+
+            let (mut stream, _) = listener.accept().unwrap();
+            answer(
+                &mut stream,
+                &[
+                    "1.2",
+                    "Network UPS Tools upsd 2.8.0",
+                    "VAR nutdev ups.status \"OL\"",
+                ],
+            )
+        });
+
+        let mut conn = ReconnectingConnection::new(config);
+        conn.connection().unwrap();
+        assert_eq!(conn.get_var("nutdev", "ups.status").unwrap().value(), "OL");
+        assert_eq!(
+            server.join().unwrap(),
+            vec!["NETVER\n", "VER\n", "GET VAR nutdev ups.status\n"]
+        );
+    }
+
+    #[test]
+    fn test_disconnect_after_timeout() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let config = ConfigBuilder::new()
+            .with_host(listener.local_addr().unwrap().into())
+            .with_read_timeout(std::time::Duration::from_millis(200))
+            .build();
+        let server = std::thread::spawn(move || {
+            // The first connection answers the request after the client gave up on it
+            let (mut stream, _) = listener.accept().unwrap();
+            answer(&mut stream, &["1.2", "Network UPS Tools upsd 2.8.0"]);
+            let mut line = String::new();
+            BufReader::new(stream.try_clone().unwrap())
+                .read_line(&mut line)
+                .unwrap();
+            std::thread::sleep(std::time::Duration::from_millis(400));
+            stream.write_all(b"VAR nutdev ups.status \"OL\"\n").unwrap();
+
+            let (mut stream2, _) = listener.accept().unwrap();
+            let lines = answer(
+                &mut stream2,
+                &[
+                    "1.2",
+                    "Network UPS Tools upsd 2.8.0",
+                    "VAR nutdev ups.load \"23\"",
+                ],
+            );
+            drop(stream);
+            lines
+        });
+
+        let mut conn = ReconnectingConnection::new(config);
+        assert!(matches!(
+            conn.get_var("nutdev", "ups.status"),
+            Err(ClientError::Nut(NutError::Timeout))
+        ));
+        // The late response to the first request isn't read as the answer to this one
+        let var = conn.get_var("nutdev", "ups.load").unwrap();
+        assert_eq!((var.name(), var.value().as_str()), ("ups.load", "23"));
+        assert_eq!(
+            server.join().unwrap(),
+            vec!["NETVER\n", "VER\n", "GET VAR nutdev ups.load\n"]
+        );
+    }
+
+    #[test]
+    fn test_server_errors_not_retried() {
+        assert!(!is_disconnected(&NutError::VarNotSupported.into()));
+        assert!(!is_disconnected(&NutError::Timeout.into()));
+        assert!(is_disconnected(&NutError::Poisoned.into()));
+        assert!(is_disconnected(
+            &std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into()
+        ));
+        assert!(is_timeout(&NutError::Timeout.into()));
+        assert!(is_timeout(
+            &std::io::Error::from(std::io::ErrorKind::TimedOut).into()
+        ));
+    }
+}
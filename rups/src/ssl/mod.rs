@@ -1,8 +1,70 @@
+use core::fmt;
+use std::sync::Arc;
+
 use crate::Config;
 
-/// The certificate validation mechanism that allows any certificate.
+/// The parameters negotiated for a TLS connection.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct TlsInfo {
+    /// The TLS protocol version (e.g. TLS 1.3).
+    pub protocol_version: rustls::ProtocolVersion,
+    /// The cipher suite (e.g. `TLS13_AES_256_GCM_SHA384`).
+    pub cipher_suite: rustls::CipherSuite,
+}
+
+impl TlsInfo {
+    /// Reads the negotiated parameters of a session, if the handshake is complete.
+    pub(crate) fn from_session(session: &rustls::ClientSession) -> Option<Self> {
+        use rustls::Session;
+
+        Some(TlsInfo {
+            protocol_version: session.get_protocol_version()?,
+            cipher_suite: session.get_negotiated_ciphersuite()?.suite,
+        })
+    }
+}
+
+/// A rustls configuration provided by the user, used as-is.
+#[derive(Clone)]
+pub(crate) struct CustomClientConfig(pub(crate) Arc<rustls::ClientConfig>);
+
+impl fmt::Debug for CustomClientConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("CustomClientConfig")
+    }
+}
+
+/// The name of the server, sent for SNI and verified against its certificate: the hostname
+/// of the host as given, never the resolved address (see
+/// [`Host::tcp_with_hostname`](crate::Host::tcp_with_hostname)).
+///
+/// This lets TLS terminators in front of upsd (e.g. proxies routing by SNI) receive the
+/// expected name. It must be a DNS name: IP addresses can't be verified nor sent.
+pub(crate) fn server_name(config: &Config) -> crate::Result<webpki::DNSName> {
+    let hostname = config
+        .host
+        .hostname()
+        .ok_or(crate::NutError::SslInvalidHostname)?;
+    Ok(webpki::DNSNameRef::try_from_ascii_str(&hostname)
+        .map_err(|_| crate::NutError::SslInvalidHostname)?
+        .to_owned())
+}
+
+/// The name of the server when its certificate is not verified against it: the hostname if
+/// it is a DNS name, or a placeholder otherwise (e.g. for IP addresses).
+pub(crate) fn insecure_server_name(config: &Config) -> webpki::DNSName {
+    server_name(config).unwrap_or_else(|_| {
+        webpki::DNSNameRef::try_from_ascii_str("www.google.com")
+            .unwrap()
+            .to_owned()
+    })
+}
+
+/// The certificate validation mechanism that allows any certificate,
+/// or only the one matching a pinned fingerprint (if any).
 pub struct InsecureCertificateValidator {
     debug: bool,
+    pinned_sha256: Option<[u8; 32]>,
 }
 
 impl InsecureCertificateValidator {
@@ -10,6 +72,7 @@ impl InsecureCertificateValidator {
     pub fn new(config: &Config) -> Self {
         InsecureCertificateValidator {
             debug: config.debug,
+            pinned_sha256: config.ssl_pinned_cert,
         }
     }
 }
@@ -18,13 +81,86 @@ impl rustls::ServerCertVerifier for InsecureCertificateValidator {
     fn verify_server_cert(
         &self,
         _roots: &rustls::RootCertStore,
-        _presented_certs: &[rustls::Certificate],
+        presented_certs: &[rustls::Certificate],
         _dns_name: webpki::DNSNameRef<'_>,
         _ocsp: &[u8],
     ) -> Result<rustls::ServerCertVerified, rustls::TLSError> {
-        if self.debug {
+        if let Some(pinned) = &self.pinned_sha256 {
+            let leaf = presented_certs
+                .first()
+                .ok_or(rustls::TLSError::NoCertificatesPresented)?;
+            let fingerprint = ring::digest::digest(&ring::digest::SHA256, &leaf.0);
+            if fingerprint.as_ref() != pinned {
+                if self.debug {
+                    eprintln!("DEBUG <- (!) Certificate does not match the pinned fingerprint");
+                }
+                return Err(rustls::TLSError::General(
+                    "Server certificate does not match the pinned fingerprint".into(),
+                ));
+            }
+            if self.debug {
+                eprintln!("DEBUG <- Certificate matches the pinned fingerprint");
+            }
+        } else if self.debug {
             eprintln!("DEBUG <- (!) Certificate received, but not verified");
         }
         Ok(rustls::ServerCertVerified::assertion())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use rustls::ServerCertVerifier;
+
+    use super::*;
+    use crate::ConfigBuilder;
+
+    fn verify(validator: &InsecureCertificateValidator, cert: &[u8]) -> bool {
+        validator
+            .verify_server_cert(
+                &rustls::RootCertStore::empty(),
+                &[rustls::Certificate(cert.to_vec())],
+                webpki::DNSNameRef::try_from_ascii_str("localhost").unwrap(),
+                &[],
+            )
+            .is_ok()
+    }
+
+    #[test]
+    fn test_pinned_cert() {
+        let mut fingerprint = [0; 32];
+        fingerprint
+            .copy_from_slice(ring::digest::digest(&ring::digest::SHA256, b"certificate").as_ref());
+        let config = ConfigBuilder::new()
+            .with_pinned_cert_sha256(fingerprint)
+            .build();
+        let validator = InsecureCertificateValidator::new(&config);
+
+        assert!(verify(&validator, b"certificate"));
+        assert!(!verify(&validator, b"another certificate"));
+    }
+
+    fn as_str(name: &webpki::DNSName) -> &str {
+        name.as_ref().into()
+    }
+
+    #[test]
+    fn test_server_name() {
+        let addr: std::net::SocketAddr = "192.0.2.10:3493".parse().unwrap();
+        let config = ConfigBuilder::new()
+            .with_host(crate::Host::tcp_with_hostname(addr, "ups.example.com"))
+            .build();
+        assert_eq!(as_str(&server_name(&config).unwrap()), "ups.example.com");
+        assert_eq!(as_str(&insecure_server_name(&config)), "ups.example.com");
+
+        let config = ConfigBuilder::new().with_host(addr.into()).build();
+        assert!(server_name(&config).is_err());
+        assert_eq!(as_str(&insecure_server_name(&config)), "www.google.com");
+    }
+
+    #[test]
+    fn test_insecure_cert() {
+        let validator = InsecureCertificateValidator::new(&ConfigBuilder::new().build());
+        assert!(verify(&validator, b"anything"));
+    }
+}
@@ -0,0 +1,131 @@
+use std::io::{Cursor, Read, Write};
+use std::sync::{Arc, Mutex};
+
+/// The bytes written by the client to a [`MockStream`].
+pub(crate) type MockOutput = Arc<Mutex<Vec<u8>>>;
+
+/// A scripted stream that returns the server lines (as many as fit in each read),
+/// and records the lines written by the client.
+///
+/// It can back both the blocking and the async clients. Once the script is exhausted,
+/// reads return end-of-file.
+pub(crate) struct MockStream {
+    input: Cursor<Vec<u8>>,
+    output: MockOutput,
+}
+
+impl MockStream {
+    pub(crate) fn new(lines: &[&str]) -> (Self, MockOutput) {
+        let input = lines.iter().map(|l| format!("{}\n", l)).collect::<String>();
+        let output = Arc::new(Mutex::new(Vec::new()));
+        let stream = MockStream {
+            input: Cursor::new(input.into_bytes()),
+            output: output.clone(),
+        };
+        (stream, output)
+    }
+}
+
+impl Read for MockStream {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.input.read(buf)
+    }
+}
+
+impl Write for MockStream {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.output.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(feature = "async")]
+impl tokio::io::AsyncRead for MockStream {
+    fn poll_read(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &mut tokio::io::ReadBuf<'_>,
+    ) -> std::task::Poll<std::io::Result<()>> {
+        std::pin::Pin::new(&mut self.get_mut().input).poll_read(cx, buf)
+    }
+}
+
+#[cfg(feature = "async")]
+impl tokio::io::AsyncWrite for MockStream {
+    fn poll_write(
+        self: std::pin::Pin<&mut Self>,
+        _cx: &mut std::task::Context<'_>,
+        buf: &[u8],
+    ) -> std::task::Poll<std::io::Result<usize>> {
+        std::task::Poll::Ready(self.get_mut().write(buf))
+    }
+
+    fn poll_flush(
+        self: std::pin::Pin<&mut Self>,
+        _cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<std::io::Result<()>> {
+        std::task::Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(
+        self: std::pin::Pin<&mut Self>,
+        _cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<std::io::Result<()>> {
+        std::task::Poll::Ready(Ok(()))
+    }
+}
+
+/// The lines written by the client so far.
+pub(crate) fn written(output: &MockOutput) -> String {
+    String::from_utf8_lossy(&output.lock().unwrap()).into_owned()
+}
+
+/// A TLS stream accepted by [`tls_server`].
+#[cfg(feature = "ssl")]
+pub(crate) type TlsServerStream = rustls::StreamOwned<rustls::ServerSession, std::net::TcpStream>;
+
+/// Spawns a server that accepts one connection, upgrades it on `STARTTLS` with a self-signed
+/// certificate for `localhost`, and answers the version probes over TLS. The thread returns
+/// the stream (to keep it open).
+#[cfg(feature = "ssl")]
+pub(crate) fn tls_server() -> (
+    std::net::SocketAddr,
+    std::thread::JoinHandle<TlsServerStream>,
+) {
+    use std::io::{BufRead, BufReader};
+
+    let certs =
+        rustls::internal::pemfile::certs(&mut &include_bytes!("ssl/testdata/localhost.crt")[..])
+            .unwrap();
+    let mut keys = rustls::internal::pemfile::pkcs8_private_keys(
+        &mut &include_bytes!("ssl/testdata/localhost.key")[..],
+    )
+    .unwrap();
+    let mut config = rustls::ServerConfig::new(rustls::NoClientAuth::new());
+    config.set_single_cert(certs, keys.remove(0)).unwrap();
+    let config = Arc::new(config);
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = std::thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut line = String::new();
+        BufReader::new(&mut stream).read_line(&mut line).unwrap();
+        assert_eq!(line, "STARTTLS\n");
+        stream.write_all(b"OK STARTTLS\n").unwrap();
+
+        let mut stream = rustls::StreamOwned::new(rustls::ServerSession::new(&config), stream);
+        for response in ["1.2", "Network UPS Tools upsd 2.8.0"] {
+            // The client waits for each response, so nothing is left in the buffer
+            let mut line = String::new();
+            BufReader::new(&mut stream).read_line(&mut line).unwrap();
+            writeln!(stream, "{}", response).unwrap();
+        }
+        stream
+    });
+    (addr, server)
+}
@@ -0,0 +1,89 @@
+use std::future::Future;
+
+use tokio::runtime::{Builder, Runtime};
+
+use crate::tokio::Connection;
+use crate::Config;
+
+/// An async NUT client connection, driven from sync code.
+///
+/// This owns a single-threaded Tokio runtime, on which the connection is established and
+/// every request is run. It must not be used from within another Tokio runtime.
+///
+/// ```no_run
+/// # fn main() -> rups::Result<()> {
+/// let config = rups::ConfigBuilder::new().build();
+/// let mut conn = rups::tokio::BlockingConnection::new(&config)?;
+/// let devices = conn.block_on(|conn| conn.list_ups())?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct BlockingConnection {
+    runtime: Runtime,
+    inner: Connection,
+}
+
+impl BlockingConnection {
+    /// Initializes a connection to a NUT server (upsd), on a new single-threaded runtime.
+    pub fn new(config: &Config) -> crate::Result<Self> {
+        let runtime = Builder::new_current_thread().enable_all().build()?;
+        let inner = runtime.block_on(Connection::new(config))?;
+        Ok(Self { runtime, inner })
+    }
+
+    /// Runs an async operation on the connection, blocking until it completes.
+    pub fn block_on<'a, F, Fut>(&'a mut self, f: F) -> Fut::Output
+    where
+        F: FnOnce(&'a mut Connection) -> Fut,
+        Fut: Future + 'a,
+    {
+        let Self { runtime, inner } = self;
+        runtime.block_on(f(inner))
+    }
+
+    /// Gracefully closes the connection.
+    pub fn close(self) -> crate::Result<()> {
+        let Self { runtime, inner } = self;
+        runtime.block_on(inner.close())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+
+    use super::*;
+    use crate::ConfigBuilder;
+
+    #[test]
+    fn test_block_on() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut requests = Vec::new();
+            for response in [
+                "1.2",
+                "Network UPS Tools upsd 2.8.0",
+                "Network UPS Tools upsd 2.8.0",
+            ] {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                requests.push(line);
+                writeln!(reader.get_mut(), "{}", response).unwrap();
+            }
+            requests.concat()
+        });
+
+        let config = ConfigBuilder::new().with_host(addr.into()).build();
+        let mut conn = BlockingConnection::new(&config).unwrap();
+        assert_eq!(
+            conn.block_on(|conn| conn.get_server_version()).unwrap(),
+            "Network UPS Tools upsd 2.8.0"
+        );
+        drop(conn);
+        assert_eq!(server.join().unwrap(), "NETVER\nVER\nVER\n");
+    }
+}
@@ -1,30 +1,148 @@
 use std::net::SocketAddr;
 
-use crate::cmd::{Command, Response};
+use crate::cmd::{Command, Response, PIPELINE_BATCH};
+use crate::proto::util::{is_mutating, join_sentence, split_sentence};
+use crate::proto::{ClientSentences, ServerSentences};
 use crate::tokio::stream::ConnectionStream;
-use crate::{Config, Host, NutError};
-use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
-use tokio::net::TcpStream;
+use crate::{ClientError, Config, Host, NutError, ServerVersion};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::{TcpSocket, TcpStream};
 
+#[cfg(feature = "async-blocking")]
+pub use bridge::BlockingConnection;
+
+#[cfg(feature = "async-blocking")]
+mod bridge;
 mod stream;
 
 /// An async NUT client connection.
+///
+/// Requests are not cancellation-safe: if a request future is dropped before the response is
+/// fully read (e.g. in a `select!` branch), the connection is poisoned and every subsequent
+/// request fails with [`NutError::Poisoned`].
 pub enum Connection {
-    /// A TCP connection.
+    /// A TCP connection (or a custom stream, see [`Connection::from_stream`]).
     Tcp(TcpConnection),
 }
 
 impl Connection {
     /// Initializes a connection to a NUT server (upsd).
+    ///
+    /// If connecting to the host (or logging in) fails, each fallback host is tried in turn
+    /// (see [`ConfigBuilder::with_fallback_hosts`](crate::ConfigBuilder::with_fallback_hosts)),
+    /// and the last error is returned if none succeeds.
     pub async fn new(config: &Config) -> crate::Result<Self> {
-        let mut conn = match &config.host {
-            Host::Tcp(host) => Self::Tcp(TcpConnection::new(config.clone(), &host.addr).await?),
+        let mut result = Self::connect(config).await;
+        for host in &config.fallback_hosts {
+            match &result {
+                Ok(_) => break,
+                Err(e) if config.debug => eprintln!("DEBUG <- (!) Connection failed: {}", e),
+                Err(_) => {}
+            }
+            let config = Config {
+                host: host.clone(),
+                ..config.clone()
+            };
+            result = Self::connect(&config).await;
+        }
+        result
+    }
+
+    /// Connects to the configured host, without fallback.
+    async fn connect(config: &Config) -> crate::Result<Self> {
+        let conn = match &config.host {
+            Host::Tcp(host) => {
+                let addr = host.resolve(config.address_family)?;
+                Self::Tcp(TcpConnection::new(config.clone(), &addr).await?)
+            }
         };
+        conn.initialize(config).await
+    }
 
-        conn.get_network_version().await?;
-        conn.login(config).await?;
+    /// Initializes a connection to a NUT server (upsd) over an already-established stream,
+    /// such as an SSH tunnel or a custom transport.
+    ///
+    /// The host of the configuration is ignored, but SSL and authentication are
+    /// performed as configured.
+    pub async fn from_stream<S>(config: &Config, stream: S) -> crate::Result<Self>
+    where
+        S: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    {
+        let conn = Self::Tcp(
+            TcpConnection::with_stream(config.clone(), ConnectionStream::Custom(Box::new(stream)))
+                .await?,
+        );
+        conn.initialize(config).await
+    }
 
-        Ok(conn)
+    /// Logs in and probes the server, as applicable.
+    async fn initialize(mut self, config: &Config) -> crate::Result<Self> {
+        if config.auto_login {
+            self.login(config).await?;
+        }
+        if config.probe_version {
+            let network_version = self.probe_network_version(config).await?;
+            let server_version = self.probe_server_version(config).await?;
+            match &mut self {
+                Self::Tcp(conn) => {
+                    conn.network_version = network_version;
+                    conn.server_version = server_version;
+                }
+            }
+        }
+        Ok(self)
+    }
+
+    /// Whether the connection is encrypted with SSL.
+    ///
+    /// Connecting fails if SSL was requested but could not be enabled, so this is only
+    /// `false` when SSL was not requested.
+    pub fn is_tls(&self) -> bool {
+        match self {
+            Self::Tcp(conn) => conn.stream.get_ref().is_ssl(),
+        }
+    }
+
+    /// The negotiated TLS protocol version and cipher suite, or `None` if SSL is not enabled.
+    #[cfg(feature = "ssl")]
+    pub fn tls_info(&self) -> Option<crate::TlsInfo> {
+        match self {
+            Self::Tcp(conn) => conn.stream.get_ref().tls_info(),
+        }
+    }
+
+    /// The underlying TCP socket, to tune options that the configuration doesn't cover
+    /// (e.g. `set_ttl`).
+    ///
+    /// This is `None` for SSL connections and custom streams (see `from_stream`).
+    ///
+    /// The socket must only be used to set options, never for I/O (e.g. with `try_read` or
+    /// `try_write`): the client buffers what it reads, so data sent or received behind its
+    /// back would desynchronize the protocol.
+    pub fn tcp_stream(&self) -> Option<&TcpStream> {
+        match self {
+            Self::Tcp(conn) => conn.stream.get_ref().tcp_stream(),
+        }
+    }
+
+    /// The network protocol version, as reported by the server when connecting.
+    ///
+    /// This is `None` if the server doesn't support `NETVER`, or if the probe is disabled
+    /// (see [`ConfigBuilder::with_version_probe`](crate::ConfigBuilder::with_version_probe)).
+    pub fn network_version(&self) -> Option<&str> {
+        match self {
+            Self::Tcp(conn) => conn.network_version.as_deref(),
+        }
+    }
+
+    /// The server NUT version, as reported by the server when connecting.
+    ///
+    /// This is `None` if the server doesn't support `VER`, or if the probe is disabled
+    /// (see [`ConfigBuilder::with_version_probe`](crate::ConfigBuilder::with_version_probe)).
+    pub fn server_version(&self) -> Option<&ServerVersion> {
+        match self {
+            Self::Tcp(conn) => conn.server_version.as_ref(),
+        }
     }
 
     /// Gracefully closes the connection.
@@ -33,9 +151,71 @@ impl Connection {
         Ok(())
     }
 
+    /// Gracefully closes the connection, giving up on the logout after the given duration.
+    ///
+    /// Unlike [`close`](Self::close), this doesn't hang if the server is gone: the socket is
+    /// then simply dropped. Since the connection is torn down either way, this never fails.
+    pub async fn close_with_timeout(mut self, timeout: std::time::Duration) -> crate::Result<()> {
+        let _ = tokio::time::timeout(timeout, self.logout()).await;
+        Ok(())
+    }
+
+    /// Queries the network version, tolerating old servers that don't support `NETVER`.
+    async fn probe_network_version(&mut self, config: &Config) -> crate::Result<Option<String>> {
+        match self.get_network_version().await {
+            Ok(version) => Ok(Some(version)),
+            Err(ClientError::Nut(NutError::UnknownCommand)) => {
+                if config.debug {
+                    eprintln!("DEBUG <- (!) NETVER is not supported by the server");
+                }
+                Ok(None)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Queries the server version, tolerating servers that don't support `VER`.
+    async fn probe_server_version(
+        &mut self,
+        config: &Config,
+    ) -> crate::Result<Option<ServerVersion>> {
+        match self.get_server_version_parsed().await {
+            Ok(version) => Ok(Some(version)),
+            Err(ClientError::Nut(NutError::UnknownCommand)) => {
+                if config.debug {
+                    eprintln!("DEBUG <- (!) VER is not supported by the server");
+                }
+                Ok(None)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Sends the configured username and password, and checks that the server accepts them.
+    ///
+    /// This is meant for connections with auto-login disabled
+    /// (see [`ConfigBuilder::with_auto_login`](crate::ConfigBuilder::with_auto_login));
+    /// otherwise, the credentials were already sent and cannot be sent again. Rejections are
+    /// returned as the specific error (e.g. [`NutError::InvalidPassword`]). Note that upsd may
+    /// only fully check the credentials when they are first used for a privileged command.
+    pub async fn verify_auth(&mut self) -> crate::Result<()> {
+        let config = match self {
+            Self::Tcp(conn) => conn.config.clone(),
+        };
+        if config.auth.is_none() {
+            return Err(NutError::UsernameRequired.into());
+        }
+        self.login(&config).await
+    }
+
     /// Sends username and password, as applicable.
     async fn login(&mut self, config: &Config) -> crate::Result<()> {
         if let Some(auth) = config.auth.clone() {
+            // upsd only accepts a password after a username, so don't bother sending it
+            if auth.username.is_empty() {
+                return Err(NutError::UsernameRequired.into());
+            }
+
             // Pass username and check for 'OK'
             self.set_username(&auth.username).await?;
 
@@ -51,18 +231,56 @@ impl Connection {
 /// A blocking TCP NUT client connection.
 pub struct TcpConnection {
     config: Config,
-    stream: ConnectionStream,
+    stream: BufReader<ConnectionStream>,
+    network_version: Option<String>,
+    pub(crate) server_version: Option<ServerVersion>,
+    pub(crate) capabilities: Option<crate::Capabilities>,
+    /// The number of responses that have yet to be read; if it is not zero when a new
+    /// request begins, a previous one was cancelled and its response may still be in the stream.
+    pending: usize,
 }
 
 impl TcpConnection {
     async fn new(config: Config, socket_addr: &SocketAddr) -> crate::Result<Self> {
-        // Create the TCP connection
-        let tcp_stream = TcpStream::connect(socket_addr).await?;
-        let mut connection = Self {
+        // Create the TCP connection, within the configured timeout
+        let connect = async {
+            match config.bind_address {
+                Some(bind_address) => {
+                    let socket = if socket_addr.is_ipv4() {
+                        TcpSocket::new_v4()?
+                    } else {
+                        TcpSocket::new_v6()?
+                    };
+                    socket.bind(bind_address)?;
+                    socket.connect(*socket_addr).await
+                }
+                None => TcpStream::connect(socket_addr).await,
+            }
+        };
+        let tcp_stream = tokio::time::timeout(config.timeout, connect)
+            .await
+            .map_err(|_| {
+                std::io::Error::new(std::io::ErrorKind::TimedOut, "connection timed out")
+            })??;
+        tcp_stream.set_nodelay(config.tcp_nodelay)?;
+        Self::with_stream(config, ConnectionStream::Plain(tcp_stream)).await
+    }
+
+    async fn with_stream(config: Config, stream: ConnectionStream) -> crate::Result<Self> {
+        let connection = Self {
             config,
-            stream: ConnectionStream::Plain(tcp_stream),
+            stream: BufReader::new(stream),
+            network_version: None,
+            server_version: None,
+            capabilities: None,
+            pending: 0,
         };
-        connection = connection.enable_ssl().await?;
+        let connection = connection.enable_ssl().await?;
+        // Never fall back to plain text when SSL was requested (e.g. if SSL support is not
+        // compiled in), since the credentials would be sent in the clear.
+        if connection.config.ssl && !connection.stream.get_ref().is_ssl() {
+            return Err(NutError::SslNotSupported.into());
+        }
         Ok(connection)
     }
 
@@ -74,48 +292,53 @@ impl TcpConnection {
             self.read_response()
                 .await
                 .map_err(|e| {
-                    if let crate::ClientError::Nut(NutError::FeatureNotConfigured) = e {
-                        crate::ClientError::Nut(NutError::SslNotSupported)
+                    if let ClientError::Nut(NutError::FeatureNotConfigured) = e {
+                        ClientError::Nut(NutError::SslNotSupported)
                     } else {
                         e
                     }
                 })?
                 .expect_ok()?;
 
-            let mut ssl_config = rustls::ClientConfig::new();
+            let ssl_config: std::sync::Arc<rustls::ClientConfig>;
             let dns_name: webpki::DNSName;
 
-            if self.config.ssl_insecure {
-                ssl_config
+            if let Some(custom) = &self.config.ssl_client_config {
+                // Use the given configuration as-is; its verifier checks the hostname
+                dns_name = crate::ssl::server_name(&self.config)?;
+                ssl_config = custom.0.clone();
+            } else if self.config.ssl_insecure || self.config.ssl_pinned_cert.is_some() {
+                let mut insecure_config = rustls::ClientConfig::new();
+                insecure_config
                     .dangerous()
                     .set_certificate_verifier(std::sync::Arc::new(
                         crate::ssl::InsecureCertificateValidator::new(&self.config),
                     ));
 
-                dns_name = webpki::DNSNameRef::try_from_ascii_str("www.google.com")
-                    .unwrap()
-                    .to_owned();
+                dns_name = crate::ssl::insecure_server_name(&self.config);
+                ssl_config = std::sync::Arc::new(insecure_config);
             } else {
-                // Try to get hostname as given (e.g. localhost can be used for strict SSL, but not 127.0.0.1)
-                let hostname = self
-                    .config
-                    .host
-                    .hostname()
-                    .ok_or(crate::ClientError::Nut(NutError::SslInvalidHostname))?;
-
-                dns_name = webpki::DNSNameRef::try_from_ascii_str(&hostname)
-                    .map_err(|_| crate::ClientError::Nut(NutError::SslInvalidHostname))?
-                    .to_owned();
-
-                ssl_config
+                // Use the hostname as given (e.g. localhost can be used for strict SSL, but not 127.0.0.1)
+                dns_name = crate::ssl::server_name(&self.config)?;
+
+                let mut strict_config = rustls::ClientConfig::new();
+                strict_config
                     .root_store
                     .add_server_trust_anchors(&webpki_roots::TLS_SERVER_ROOTS);
+
+                ssl_config = std::sync::Arc::new(strict_config);
             };
 
-            let config = tokio_rustls::TlsConnector::from(std::sync::Arc::new(ssl_config));
+            let config = tokio_rustls::TlsConnector::from(ssl_config);
 
-            // Wrap and override the TCP stream
-            self.stream = self.stream.upgrade_ssl(config, dns_name.as_ref()).await?;
+            // Wrap and override the TCP stream. Nothing is buffered at this point,
+            // since the server waits for the TLS handshake.
+            self.stream = BufReader::new(
+                self.stream
+                    .into_inner()
+                    .upgrade_ssl(config, dns_name.as_ref())
+                    .await?,
+            );
         }
         Ok(self)
     }
@@ -125,65 +348,874 @@ impl TcpConnection {
         Ok(self)
     }
 
+    pub(crate) fn config(&self) -> &Config {
+        &self.config
+    }
+
     pub(crate) async fn write_cmd(&mut self, line: Command<'_>) -> crate::Result<()> {
-        let line = format!("{}\n", line);
-        if self.config.debug {
-            eprint!("DEBUG -> {}", line);
+        self.write_line(&line.to_string()).await
+    }
+
+    pub(crate) async fn write_line(&mut self, line: &str) -> crate::Result<()> {
+        self.write_lines(&[line]).await
+    }
+
+    /// Writes several command lines at once, without waiting for their responses (pipelining).
+    pub(crate) async fn write_lines<S: AsRef<str>>(&mut self, lines: &[S]) -> crate::Result<()> {
+        if lines
+            .iter()
+            .any(|line| line.as_ref().contains(&['\n', '\r'][..]))
+        {
+            return Err(NutError::generic("Command line cannot contain a line break").into());
+        }
+        if self.config.read_only && lines.iter().any(|line| is_mutating(line.as_ref())) {
+            return Err(NutError::ReadOnly.into());
+        }
+        if self.pending > 0 {
+            return Err(NutError::Poisoned.into());
+        }
+        let mut buf = String::new();
+        for line in lines {
+            let line = format!("{}\n", line.as_ref());
+            if self.config.debug {
+                eprint!("DEBUG -> {}", line);
+            }
+            buf.push_str(&line);
         }
-        self.stream.write_all(line.as_bytes()).await?;
+        // Decremented as each response is fully read
+        self.pending = lines.len();
+        self.stream.write_all(buf.as_bytes()).await?;
         self.stream.flush().await?;
         Ok(())
     }
 
-    async fn parse_line(
-        reader: &mut BufReader<&mut ConnectionStream>,
+    /// Writes a typed protocol sentence.
+    pub(crate) async fn write_sentence(&mut self, sentence: &ServerSentences) -> crate::Result<()> {
+        self.write_line(&join_sentence(sentence.encode())).await
+    }
+
+    /// Reads typed protocol sentences, up to and including the first one for which
+    /// `is_last` returns `true`.
+    ///
+    /// Fails if the server responds with an error, or with a sentence that cannot be decoded.
+    pub(crate) async fn read_sentences_until<F>(
+        &mut self,
+        is_last: F,
+    ) -> crate::Result<Vec<ClientSentences>>
+    where
+        F: Fn(&ClientSentences) -> bool,
+    {
+        // No cap on the number of sentences, nor timeout: wrap the request in
+        // `tokio::time::timeout` to bound the time spent on a stalled server.
+        let mut sentences = Vec::new();
+        loop {
+            let args = Self::parse_line(&mut self.stream, self.config.debug).await?;
+            let sentence = ClientSentences::decode(args).ok_or(NutError::UnexpectedResponse)?;
+            if let ClientSentences::RespondErr { .. } = sentence {
+                self.response_done();
+                return Err(NutError::from(sentence).into());
+            }
+            let done = is_last(&sentence);
+            sentences.push(sentence);
+            if done {
+                break;
+            }
+        }
+        self.response_done();
+        Ok(sentences)
+    }
+
+    /// Marks a response as fully read.
+    fn response_done(&mut self) {
+        self.pending = self.pending.saturating_sub(1);
+    }
+
+    async fn parse_line<R: AsyncBufRead + Unpin>(
+        reader: &mut R,
         debug: bool,
     ) -> crate::Result<Vec<String>> {
         let mut raw = String::new();
-        reader.read_line(&mut raw).await?;
+        if reader.read_line(&mut raw).await? == 0 {
+            return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+        }
         if debug {
             eprint!("DEBUG <- {}", raw);
         }
-        raw = raw.trim_end_matches('\n').to_string(); // Strip off \n
+        raw = raw.trim_end_matches(&['\r', '\n'][..]).to_string(); // Strip off \n or \r\n
 
         // Parse args by splitting whitespace, minding quotes for args with multiple words
-        let args = shell_words::split(&raw)
-            .map_err(|e| NutError::generic(format!("Parsing server response failed: {}", e)))?;
+        let args = split_sentence(&raw)
+            .ok_or_else(|| NutError::generic(format!("Parsing server response failed: {}", raw)))?;
 
         Ok(args)
     }
 
     pub(crate) async fn read_response(&mut self) -> crate::Result<Response> {
-        let mut reader = BufReader::new(&mut self.stream);
-        let args = Self::parse_line(&mut reader, self.config.debug).await?;
+        let args = Self::parse_line(&mut self.stream, self.config.debug).await?;
+        self.response_done();
         Response::from_args(args)
     }
 
+    /// Sends single-line requests in pipelined batches, and reads their responses.
+    ///
+    /// Error responses are returned in place, as with [`read_responses`](Self::read_responses).
+    pub(crate) async fn pipeline_responses(
+        &mut self,
+        queries: &[String],
+    ) -> crate::Result<Vec<crate::Result<Response>>> {
+        let mut responses = Vec::with_capacity(queries.len());
+        for batch in queries.chunks(PIPELINE_BATCH) {
+            self.write_lines(batch).await?;
+            responses.extend(self.read_responses(batch.len()).await?);
+        }
+        Ok(responses)
+    }
+
+    /// Sends `LIST` queries in pipelined batches, and reads their lists.
+    ///
+    /// Errors in a list are returned in place, so every list is read.
+    pub(crate) async fn pipeline_lists<'q, Q: AsRef<[&'q str]>>(
+        &mut self,
+        queries: &[Q],
+    ) -> crate::Result<Vec<crate::Result<Vec<Response>>>> {
+        let mut lists = Vec::with_capacity(queries.len());
+        for batch in queries.chunks(PIPELINE_BATCH) {
+            let lines: Vec<String> = batch
+                .iter()
+                .map(|query| Command::List(query.as_ref()).to_string())
+                .collect();
+            self.write_lines(&lines).await?;
+            for query in batch {
+                lists.push(self.read_list(query.as_ref()).await);
+            }
+        }
+        Ok(lists)
+    }
+
+    /// Reads the responses to `count` pipelined requests.
+    ///
+    /// Error responses don't stop the following ones from being read, which leaves the
+    /// stream in a clean state; only a failure to read from the stream is returned early.
+    pub(crate) async fn read_responses(
+        &mut self,
+        count: usize,
+    ) -> crate::Result<Vec<crate::Result<Response>>> {
+        let mut responses = Vec::with_capacity(count);
+        for _ in 0..count {
+            let args = Self::parse_line(&mut self.stream, self.config.debug).await?;
+            self.response_done();
+            responses.push(Response::from_args(args));
+        }
+        Ok(responses)
+    }
+
     pub(crate) async fn read_plain_response(&mut self) -> crate::Result<String> {
-        let mut reader = BufReader::new(&mut self.stream);
-        let args = Self::parse_line(&mut reader, self.config.debug).await?;
+        let args = Self::parse_line(&mut self.stream, self.config.debug).await?;
+        self.response_done();
+        if args.first().map(String::as_str) == Some("ERR") {
+            return Err(Response::from_args(args)
+                .err()
+                .unwrap_or_else(|| NutError::UnexpectedResponse.into()));
+        }
         Ok(args.join(" "))
     }
 
+    pub(crate) async fn read_raw_response(&mut self) -> crate::Result<Vec<Vec<String>>> {
+        let mut lines = Vec::new();
+
+        loop {
+            let args = Self::parse_line(&mut self.stream, self.config.debug).await?;
+            let word = args.first().cloned().unwrap_or_default();
+            if word == "ERR" {
+                self.response_done();
+                return Err(Response::from_args(args)
+                    .err()
+                    .unwrap_or_else(|| NutError::UnexpectedResponse.into()));
+            }
+            let is_list = !lines.is_empty() || word == "BEGIN";
+            lines.push(args);
+            if !is_list || word == "END" {
+                break;
+            }
+        }
+
+        self.response_done();
+        Ok(lines)
+    }
+
     pub(crate) async fn read_list(&mut self, query: &[&str]) -> crate::Result<Vec<Response>> {
-        let mut reader = BufReader::new(&mut self.stream);
-        let args = Self::parse_line(&mut reader, self.config.debug).await?;
+        let args = Self::parse_line(&mut self.stream, self.config.debug).await?;
 
-        Response::from_args(args)?.expect_begin_list(query)?;
+        let begin = Response::from_args(args);
+        if begin.is_err() {
+            // The server didn't begin a list (e.g. it refused the request)
+            self.response_done();
+        }
+        begin?.expect_begin_list(query)?;
         let mut lines: Vec<Response> = Vec::new();
 
         loop {
-            let args = Self::parse_line(&mut reader, self.config.debug).await?;
+            let args = Self::parse_line(&mut self.stream, self.config.debug).await?;
+            if args.first().map(String::as_str) == Some("ERR") {
+                // The server aborted the list (e.g. the driver went stale mid-list)
+                self.response_done();
+            }
             let resp = Response::from_args(args)?;
 
             match resp {
                 Response::EndList(_) => {
+                    resp.expect_end_list(query)?;
                     break;
                 }
                 _ => lines.push(resp),
             }
         }
 
+        self.response_done();
         Ok(lines)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::io::AsyncWriteExt;
+
+    use super::*;
+    use crate::test_util::{written, MockOutput, MockStream};
+    use crate::{Auth, ConfigBuilder, OwnedCommand, UpsStatusFlag};
+
+    /// Connects a client to a scripted stream, with the default configuration.
+    ///
+    /// The version probes are answered and left out of the recorded output, so the script only
+    /// holds the responses to the requests of the test.
+    async fn mock_conn(lines: &[&str]) -> (Connection, MockOutput) {
+        let mut script = vec!["1.2", "Network UPS Tools upsd 2.8.0"];
+        script.extend_from_slice(lines);
+        let (stream, output) = MockStream::new(&script);
+        let conn = Connection::from_stream(&ConfigBuilder::new().build(), stream)
+            .await
+            .unwrap();
+        output.lock().unwrap().clear();
+        (conn, output)
+    }
+
+    #[tokio::test]
+    async fn test_from_stream() {
+        let (mut conn, output) =
+            mock_conn(&["BEGIN LIST UPS", "UPS nutdev \"Test UPS\"", "END LIST UPS"]).await;
+
+        assert_eq!(
+            conn.list_ups().await.unwrap(),
+            vec![("nutdev".into(), "Test UPS".into())]
+        );
+        assert_eq!(written(&output), "LIST UPS\n");
+        assert!(!conn.is_tls());
+        assert!(conn.tcp_stream().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_ssl_not_enabled() {
+        let (stream, _) = MockStream::new(&["ERR FEATURE-NOT-CONFIGURED"]);
+        let mut config = ConfigBuilder::new().build();
+        config.ssl = true;
+
+        // Whether the server refuses, or SSL support is not compiled in,
+        // the connection must not carry on in plain text
+        assert!(matches!(
+            Connection::from_stream(&config, stream).await,
+            Err(ClientError::Nut(NutError::SslNotSupported))
+        ));
+    }
+
+    #[tokio::test]
+    #[cfg(feature = "async-ssl")]
+    async fn test_ssl_handshake_failed() {
+        let (stream, output) = MockStream::new(&["OK STARTTLS", "garbage", "1.2"]);
+        let mut config = ConfigBuilder::new()
+            .with_ssl(true)
+            .with_insecure_ssl(true)
+            .build();
+        config.auth = Some(Auth::new("admin".into(), Some("secret".into())));
+
+        assert!(matches!(
+            Connection::from_stream(&config, stream).await,
+            Err(ClientError::Nut(NutError::SslHandshakeFailed(_)))
+        ));
+        assert!(!written(&output).contains("secret"));
+    }
+
+    #[tokio::test]
+    #[cfg(feature = "async-ssl")]
+    async fn test_custom_rustls_config() {
+        // The custom configuration takes precedence over `with_insecure_ssl`,
+        // so the host must have a DNS name for its verifier
+        let (stream, output) = MockStream::new(&["OK STARTTLS"]);
+        let config = ConfigBuilder::new()
+            .with_host(
+                "127.0.0.1:3493"
+                    .parse::<std::net::SocketAddr>()
+                    .unwrap()
+                    .into(),
+            )
+            .with_ssl(true)
+            .with_insecure_ssl(true)
+            .with_rustls_config(std::sync::Arc::new(rustls::ClientConfig::new()))
+            .build();
+
+        assert!(matches!(
+            Connection::from_stream(&config, stream).await,
+            Err(ClientError::Nut(NutError::SslInvalidHostname))
+        ));
+        assert_eq!(written(&output), "STARTTLS\n");
+    }
+
+    #[tokio::test]
+    #[cfg(feature = "async-ssl")]
+    async fn test_tls_info() {
+        let (addr, server) = crate::test_util::tls_server();
+        let config = ConfigBuilder::new()
+            .with_host(addr.into())
+            .with_ssl(true)
+            .with_insecure_ssl(true)
+            .build();
+        let conn = Connection::new(&config).await.unwrap();
+        let _stream = server.join().unwrap();
+
+        assert!(conn.is_tls());
+        let info = conn.tls_info().unwrap();
+        assert_eq!(info.protocol_version, rustls::ProtocolVersion::TLSv1_3);
+    }
+
+    #[tokio::test]
+    async fn test_login_on_access_denied_failed() {
+        let (stream, output) = MockStream::new(&[
+            "OK",
+            "OK",
+            "1.2",
+            "Network UPS Tools upsd 2.8.0",
+            "ERR ACCESS-DENIED",
+            "ERR INVALID-PASSWORD",
+        ]);
+        let config = ConfigBuilder::new()
+            .with_auth(Some(Auth::new("admin".into(), Some("secret".into()))))
+            .with_auto_login_on_access_denied(true)
+            .build();
+        let mut conn = Connection::from_stream(&config, stream).await.unwrap();
+
+        assert!(matches!(
+            conn.list_vars("nutdev").await,
+            Err(ClientError::Nut(NutError::InvalidPassword))
+        ));
+        assert_eq!(
+            written(&output),
+            "USERNAME admin\nPASSWORD secret\nNETVER\nVER\nLIST VAR nutdev\nLOGIN nutdev\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_login_without_username() {
+        let (stream, output) = MockStream::new(&[]);
+        let config = ConfigBuilder::new()
+            .with_auth(Some(Auth::new(String::new(), Some("secret".into()))))
+            .build();
+
+        assert!(matches!(
+            Connection::from_stream(&config, stream).await,
+            Err(ClientError::Nut(NutError::UsernameRequired))
+        ));
+        assert_eq!(written(&output), "");
+    }
+
+    #[tokio::test]
+    async fn test_version_probe() {
+        let (stream, output) = MockStream::new(&["1.3", "Network UPS Tools upsd 2.8.1"]);
+        let conn = Connection::from_stream(&ConfigBuilder::new().build(), stream)
+            .await
+            .unwrap();
+        assert_eq!(written(&output), "NETVER\nVER\n");
+        assert_eq!(conn.network_version(), Some("1.3"));
+        assert!(conn.server_version().unwrap().is_at_least((2, 8, 1)));
+
+        let (stream, output) = MockStream::new(&["ERR UNKNOWN-COMMAND", "ERR UNKNOWN-COMMAND"]);
+        let conn = Connection::from_stream(&ConfigBuilder::new().build(), stream)
+            .await
+            .unwrap();
+        assert_eq!(written(&output), "NETVER\nVER\n");
+        assert_eq!(conn.network_version(), None);
+        assert_eq!(conn.server_version(), None);
+    }
+
+    #[tokio::test]
+    async fn test_mock_list() {
+        let (stream, output) = MockStream::new(&[
+            "BEGIN LIST VAR nutdev",
+            "VAR nutdev ups.status \"OL CHRG\"",
+            "END LIST VAR nutdev",
+        ]);
+        let config = ConfigBuilder::new().with_version_probe(false).build();
+        let mut conn = Connection::from_stream(&config, stream).await.unwrap();
+
+        let vars = conn.list_vars("nutdev").await.unwrap();
+        assert_eq!(vars.len(), 1);
+        assert_eq!(vars[0].value(), "OL CHRG");
+        assert_eq!(written(&output), "LIST VAR nutdev\n");
+    }
+
+    #[tokio::test]
+    async fn test_mock_login() {
+        let (stream, output) = MockStream::new(&["OK", "OK", "NUMLOGINS nutdev 1"]);
+        let config = ConfigBuilder::new()
+            .with_auth(Some(Auth::new("monuser".into(), Some("my secret".into()))))
+            .with_version_probe(false)
+            .build();
+        let mut conn = Connection::from_stream(&config, stream).await.unwrap();
+
+        assert_eq!(conn.get_num_logins("nutdev").await.unwrap(), 1);
+        assert_eq!(
+            written(&output),
+            "USERNAME monuser\nPASSWORD \"my secret\"\nGET NUMLOGINS nutdev\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_mock_error() {
+        let (stream, output) =
+            MockStream::new(&["ERR VAR-NOT-SUPPORTED", "VAR nutdev ups.load \"23\""]);
+        let config = ConfigBuilder::new().with_version_probe(false).build();
+        let mut conn = Connection::from_stream(&config, stream).await.unwrap();
+
+        assert!(matches!(
+            conn.get_var("nutdev", "input.voltage").await,
+            Err(ClientError::Nut(NutError::VarNotSupported))
+        ));
+        assert_eq!(
+            conn.get_var("nutdev", "ups.load").await.unwrap().value(),
+            "23"
+        );
+        assert_eq!(
+            written(&output),
+            "GET VAR nutdev input.voltage\nGET VAR nutdev ups.load\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_list_ups() {
+        let (mut conn, output) = mock_conn(&[
+            "BEGIN LIST UPS",
+            "UPS nutdev1 \"First UPS\"",
+            "UPS nutdev2 \"Second UPS\"",
+            "END LIST UPS",
+            "ERR ACCESS-DENIED",
+            "NUMLOGINS nutdev1 1",
+        ])
+        .await;
+
+        assert_eq!(
+            conn.list_ups().await.unwrap(),
+            vec![
+                ("nutdev1".to_string(), "First UPS".to_string()),
+                ("nutdev2".to_string(), "Second UPS".to_string()),
+            ]
+        );
+        assert!(matches!(
+            conn.list_ups().await,
+            Err(ClientError::Nut(NutError::AccessDenied))
+        ));
+        // The refused request doesn't poison the connection
+        assert_eq!(conn.get_num_logins("nutdev1").await.unwrap(), 1);
+        assert_eq!(
+            written(&output),
+            "LIST UPS\nLIST UPS\nGET NUMLOGINS nutdev1\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_list_editable() {
+        let (mut conn, output) = mock_conn(&[
+            "BEGIN LIST RW nutdev",
+            "RW nutdev input.sensitivity \"normal\"",
+            "RW nutdev input.transfer.low \"95\"",
+            "RW nutdev ups.id \"Rack 1\"",
+            "END LIST RW nutdev",
+            "TYPE nutdev input.sensitivity RW ENUM",
+            "TYPE nutdev input.transfer.low RW RANGE",
+            "TYPE nutdev ups.id RW STRING:10",
+            "BEGIN LIST ENUM nutdev input.sensitivity",
+            "ENUM nutdev input.sensitivity \"low\"",
+            "ENUM nutdev input.sensitivity \"normal\"",
+            "END LIST ENUM nutdev input.sensitivity",
+            "BEGIN LIST RANGE nutdev input.transfer.low",
+            "RANGE nutdev input.transfer.low \"80\" \"90\"",
+            "RANGE nutdev input.transfer.low \"95\" \"105\"",
+            "END LIST RANGE nutdev input.transfer.low",
+        ])
+        .await;
+
+        let editable = conn.list_editable("nutdev").await.unwrap();
+        assert_eq!(editable.len(), 3);
+        assert_eq!(editable[0].name, "input.sensitivity");
+        assert_eq!(editable[0].value, "normal");
+        assert_eq!(editable[0].enum_values, vec!["low", "normal"]);
+        assert!(editable[0].ranges.is_empty());
+        assert_eq!(
+            editable[1].ranges,
+            vec![
+                crate::VariableRange("80".into(), "90".into()),
+                crate::VariableRange("95".into(), "105".into()),
+            ]
+        );
+        assert!(editable[1].enum_values.is_empty());
+        assert_eq!(editable[2].value, "Rack 1");
+        assert!(editable[2].enum_values.is_empty() && editable[2].ranges.is_empty());
+        // The types, then the lists, are each requested at once
+        assert_eq!(
+            written(&output),
+            "LIST RW nutdev\n\
+            GET TYPE nutdev input.sensitivity\nGET TYPE nutdev input.transfer.low\n\
+            GET TYPE nutdev ups.id\n\
+            LIST ENUM nutdev input.sensitivity\nLIST RANGE nutdev input.transfer.low\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_list_editable_error() {
+        let (mut conn, output) = mock_conn(&[
+            "BEGIN LIST RW nutdev",
+            "RW nutdev input.sensitivity \"normal\"",
+            "RW nutdev ups.id \"Rack 1\"",
+            "END LIST RW nutdev",
+            "ERR VAR-NOT-SUPPORTED",
+            "TYPE nutdev ups.id RW STRING:10",
+            "NUMLOGINS nutdev 1",
+        ])
+        .await;
+
+        assert!(matches!(
+            conn.list_editable("nutdev").await,
+            Err(ClientError::Nut(NutError::VarNotSupported))
+        ));
+        // Every pipelined response was read, so the connection is still usable
+        assert_eq!(conn.get_num_logins("nutdev").await.unwrap(), 1);
+        assert_eq!(
+            written(&output),
+            "LIST RW nutdev\n\
+            GET TYPE nutdev input.sensitivity\nGET TYPE nutdev ups.id\n\
+            GET NUMLOGINS nutdev\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_list_all_clients() {
+        let (mut conn, output) = mock_conn(&[
+            "BEGIN LIST UPS",
+            "UPS nutdev1 \"First UPS\"",
+            "UPS nutdev2 \"Second UPS\"",
+            "UPS nutdev3 \"Third UPS\"",
+            "END LIST UPS",
+            "BEGIN LIST CLIENT nutdev1",
+            "CLIENT nutdev1 127.0.0.1",
+            "CLIENT nutdev1 ::1",
+            "END LIST CLIENT nutdev1",
+            "BEGIN LIST CLIENT nutdev2",
+            "END LIST CLIENT nutdev2",
+            "BEGIN LIST CLIENT nutdev3",
+            "CLIENT nutdev3 192.168.1.20",
+            "END LIST CLIENT nutdev3",
+        ])
+        .await;
+
+        assert_eq!(
+            conn.list_all_clients().await.unwrap(),
+            vec![
+                ("nutdev1".to_string(), "127.0.0.1".to_string()),
+                ("nutdev1".to_string(), "::1".to_string()),
+                ("nutdev3".to_string(), "192.168.1.20".to_string()),
+            ]
+        );
+        assert_eq!(
+            written(&output),
+            "LIST UPS\nLIST CLIENT nutdev1\nLIST CLIENT nutdev2\nLIST CLIENT nutdev3\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_list_all_clients_error() {
+        let (mut conn, output) = mock_conn(&[
+            "BEGIN LIST UPS",
+            "UPS nutdev1 \"First UPS\"",
+            "UPS nutdev2 \"Second UPS\"",
+            "UPS nutdev3 \"Third UPS\"",
+            "END LIST UPS",
+            "BEGIN LIST CLIENT nutdev1",
+            "CLIENT nutdev1 127.0.0.1",
+            "END LIST CLIENT nutdev1",
+            "ERR ACCESS-DENIED",
+        ])
+        .await;
+
+        assert!(matches!(
+            conn.list_all_clients().await,
+            Err(ClientError::Nut(NutError::AccessDenied))
+        ));
+        assert_eq!(
+            written(&output),
+            "LIST UPS\nLIST CLIENT nutdev1\nLIST CLIENT nutdev2\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_list_ups_filtered() {
+        let (mut conn, output) = mock_conn(&[
+            "BEGIN LIST UPS",
+            "UPS pdu1 \"Rack PDU\"",
+            "UPS nutdev \"Main UPS\"",
+            "UPS pdu2 \"Spare PDU\"",
+            "END LIST UPS",
+            "BEGIN LIST UPS",
+            "UPS pdu1 \"Rack PDU\"",
+            "UPS nutdev \"Main UPS\"",
+            "UPS pdu2 \"Spare PDU\"",
+            "END LIST UPS",
+            "BEGIN LIST UPS",
+            "UPS pdu1 \"Rack PDU\"",
+            "UPS nutdev \"Main UPS\"",
+            "UPS pdu2 \"Spare PDU\"",
+            "END LIST UPS",
+        ])
+        .await;
+
+        let names = |devices: Vec<(String, String)>| {
+            devices
+                .into_iter()
+                .map(|(name, _)| name)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            names(conn.list_ups_filtered("pdu").await.unwrap()),
+            vec!["pdu1", "pdu2"]
+        );
+        assert_eq!(
+            names(conn.list_ups_filtered("").await.unwrap()),
+            vec!["pdu1", "nutdev", "pdu2"]
+        );
+        assert!(conn.list_ups_filtered("ups").await.unwrap().is_empty());
+        assert_eq!(written(&output), "LIST UPS\nLIST UPS\nLIST UPS\n");
+    }
+
+    #[tokio::test]
+    async fn test_raw() {
+        let (mut conn, output) = mock_conn(&[
+            "NUMLOGINS nutdev 1",
+            "BEGIN LIST CLIENT nutdev",
+            "CLIENT nutdev 127.0.0.1",
+            "CLIENT nutdev ::1",
+            "END LIST CLIENT nutdev",
+            "ERR UNKNOWN-UPS",
+        ])
+        .await;
+
+        assert_eq!(
+            conn.raw("GET NUMLOGINS nutdev").await.unwrap(),
+            vec![vec!["NUMLOGINS", "nutdev", "1"]]
+        );
+        assert_eq!(
+            conn.raw("LIST CLIENT nutdev").await.unwrap(),
+            vec![
+                vec!["BEGIN", "LIST", "CLIENT", "nutdev"],
+                vec!["CLIENT", "nutdev", "127.0.0.1"],
+                vec!["CLIENT", "nutdev", "::1"],
+                vec!["END", "LIST", "CLIENT", "nutdev"],
+            ]
+        );
+        assert!(matches!(
+            conn.raw("GET NUMLOGINS upsdev").await,
+            Err(ClientError::Nut(NutError::UnknownUps))
+        ));
+        assert_eq!(
+            written(&output),
+            "GET NUMLOGINS nutdev\nLIST CLIENT nutdev\nGET NUMLOGINS upsdev\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_raw_line_break() {
+        let (mut conn, output) = mock_conn(&[]).await;
+
+        assert!(conn.raw("GET NUMLOGINS nutdev\nFSD nutdev").await.is_err());
+        assert!(conn.raw("GET NUMLOGINS nutdev\rFSD nutdev").await.is_err());
+        assert_eq!(written(&output), "");
+    }
+
+    #[tokio::test]
+    async fn test_send_command() {
+        let (mut conn, output) = mock_conn(&[
+            "BEGIN LIST VAR nutdev",
+            "VAR nutdev ups.status \"OL\"",
+            "END LIST VAR nutdev",
+            "DESC nutdev ups.status \"UPS status\"",
+        ])
+        .await;
+
+        let rows = conn
+            .send_command(&OwnedCommand::list(vec!["VAR".into(), "nutdev".into()]))
+            .await
+            .unwrap();
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows[1], vec!["VAR", "nutdev", "ups.status", "OL"]);
+        let cmd = OwnedCommand::get(vec!["DESC".into(), "nutdev".into(), "ups.status".into()]);
+        assert_eq!(
+            conn.send_command(&cmd).await.unwrap(),
+            vec![vec!["DESC", "nutdev", "ups.status", "UPS status"]]
+        );
+        assert_eq!(
+            written(&output),
+            "LIST VAR nutdev\nGET DESC nutdev ups.status\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_watch_var() {
+        let (mut conn, _) = mock_conn(&[
+            "VAR nutdev battery.charge 100",
+            "VAR nutdev battery.charge 100",
+            "VAR nutdev battery.charge 90",
+            "VAR nutdev battery.charge 80",
+        ])
+        .await;
+
+        let mut values = Vec::new();
+        conn.watch_var(
+            "nutdev",
+            "battery.charge",
+            Duration::from_millis(1),
+            |var| {
+                values.push(var.value());
+                values.len() < 3
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(values, vec!["100", "90", "80"]);
+    }
+
+    #[tokio::test]
+    async fn test_read_list_aborted() {
+        let (mut conn, _) = mock_conn(&[
+            "BEGIN LIST VAR nutdev",
+            "VAR nutdev battery.charge 100",
+            "ERR DATA-STALE",
+            "1.2",
+        ])
+        .await;
+
+        assert!(matches!(
+            conn.list_vars("nutdev").await,
+            Err(ClientError::Nut(NutError::DataStale))
+        ));
+        assert_eq!(conn.get_network_version().await.unwrap(), "1.2");
+    }
+
+    #[tokio::test]
+    async fn test_connect_timeout() {
+        // Once the backlog of a listener that never accepts is full, further connection
+        // attempts are left unanswered
+        let listener =
+            socket2::Socket::new(socket2::Domain::IPV4, socket2::Type::STREAM, None).unwrap();
+        listener
+            .bind(&"127.0.0.1:0".parse::<SocketAddr>().unwrap().into())
+            .unwrap();
+        listener.listen(0).unwrap();
+        let addr = listener.local_addr().unwrap().as_socket().unwrap();
+        let _backlog: Vec<_> = (0..8)
+            .filter_map(|_| {
+                std::net::TcpStream::connect_timeout(&addr, Duration::from_millis(100)).ok()
+            })
+            .collect();
+
+        let config = ConfigBuilder::new()
+            .with_host(addr.into())
+            .with_timeout(Duration::from_millis(100))
+            .build();
+        let start = std::time::Instant::now();
+        let result = Connection::new(&config).await;
+        assert!(
+            matches!(&result, Err(ClientError::Io(e)) if e.kind() == std::io::ErrorKind::TimedOut)
+        );
+        assert!(start.elapsed() < Duration::from_secs(2));
+    }
+
+    #[tokio::test]
+    async fn test_close_with_timeout() {
+        // The server answers the version probes, then never answers the logout
+        let (client, mut server) = tokio::io::duplex(1024);
+        server
+            .write_all(b"1.2\nNetwork UPS Tools upsd 2.8.0\n")
+            .await
+            .unwrap();
+        let config = ConfigBuilder::new().build();
+        let conn = Connection::from_stream(&config, client).await.unwrap();
+
+        conn.close_with_timeout(Duration::from_millis(10))
+            .await
+            .unwrap();
+        drop(server);
+    }
+
+    #[tokio::test]
+    async fn test_status_monitor() {
+        let (conn, _) = mock_conn(&[
+            "VAR nutdev ups.status OL",
+            "VAR nutdev ups.status OL",
+            "VAR nutdev ups.status \"OB LB\"",
+            "ERR DRIVER-NOT-CONNECTED",
+        ])
+        .await;
+
+        let (handle, mut receiver) = conn.spawn_status_monitor("nutdev", Duration::from_millis(1));
+        let mut updates = Vec::new();
+        while let Some(status) = receiver.recv().await {
+            updates.push(status);
+        }
+        assert_eq!(
+            updates,
+            vec![
+                UpsStatusFlag::parse_status("OL"),
+                UpsStatusFlag::parse_status("OB LB")
+            ]
+        );
+        assert!(matches!(
+            handle.await.unwrap(),
+            Err(ClientError::Nut(NutError::DriverNotConnected))
+        ));
+
+        // Dropping the receiver closes the connection
+        let (conn, output) = mock_conn(&["VAR nutdev ups.status OL", "OK Goodbye"]).await;
+        let (handle, mut receiver) = conn.spawn_status_monitor("nutdev", Duration::from_secs(3600));
+        assert!(receiver.recv().await.is_some());
+        drop(receiver);
+        handle.await.unwrap().unwrap();
+        assert_eq!(written(&output), "GET VAR nutdev ups.status\nLOGOUT\n");
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_request_poisons_connection() {
+        let (client, mut server) = tokio::io::duplex(1024);
+        server
+            .write_all(b"1.2\nNetwork UPS Tools upsd 2.8.0\n")
+            .await
+            .unwrap();
+        let config = ConfigBuilder::new().build();
+        let mut conn = Connection::from_stream(&config, client).await.unwrap();
+
+        // The server never answers, so the request is cancelled mid-response
+        let cancelled = tokio::time::timeout(Duration::from_millis(10), conn.list_ups()).await;
+        assert!(cancelled.is_err());
+
+        assert!(matches!(
+            conn.get_network_version().await,
+            Err(ClientError::Nut(NutError::Poisoned))
+        ));
+    }
+}
@@ -4,17 +4,52 @@ use std::task::{Context, Poll};
 use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
 use tokio::net::TcpStream;
 
+/// A stream that is both readable and writable, such as a custom transport.
+pub trait AsyncReadWrite: AsyncRead + AsyncWrite + Send + Unpin {}
+
+impl<T: AsyncRead + AsyncWrite + Send + Unpin> AsyncReadWrite for T {}
+
 /// A wrapper for various Tokio stream types.
 pub enum ConnectionStream {
     /// A plain TCP stream.
     Plain(TcpStream),
 
+    /// A stream provided by the user.
+    Custom(Box<dyn AsyncReadWrite>),
+
     /// A stream wrapped with SSL using `rustls`.
     #[cfg(feature = "async-ssl")]
     Ssl(Box<tokio_rustls::client::TlsStream<ConnectionStream>>),
 }
 
 impl ConnectionStream {
+    /// The plain TCP stream, if the stream is neither custom nor wrapped with SSL.
+    pub fn tcp_stream(&self) -> Option<&TcpStream> {
+        match self {
+            Self::Plain(stream) => Some(stream),
+            _ => None,
+        }
+    }
+
+    /// Whether the stream is wrapped with SSL.
+    pub fn is_ssl(&self) -> bool {
+        match self {
+            Self::Plain(_) | Self::Custom(_) => false,
+            #[cfg(feature = "async-ssl")]
+            Self::Ssl(_) => true,
+        }
+    }
+
+    /// The negotiated TLS parameters, if the stream is wrapped with SSL.
+    #[cfg(feature = "ssl")]
+    pub fn tls_info(&self) -> Option<crate::TlsInfo> {
+        match self {
+            Self::Plain(_) | Self::Custom(_) => None,
+            #[cfg(feature = "async-ssl")]
+            Self::Ssl(stream) => crate::TlsInfo::from_session(stream.get_ref().1),
+        }
+    }
+
     /// Wraps the current stream with SSL using `rustls`.
     #[cfg(feature = "async-ssl")]
     pub async fn upgrade_ssl(
@@ -26,7 +61,7 @@ impl ConnectionStream {
             config
                 .connect(dns_name, self)
                 .await
-                .map_err(crate::ClientError::Io)?,
+                .map_err(|e| crate::NutError::SslHandshakeFailed(e.to_string()))?,
         )))
     }
 }
@@ -42,6 +77,10 @@ impl AsyncRead for ConnectionStream {
                 let pinned = Pin::new(stream);
                 pinned.poll_read(cx, buf)
             }
+            Self::Custom(stream) => {
+                let pinned = Pin::new(stream);
+                pinned.poll_read(cx, buf)
+            }
             #[cfg(feature = "async-ssl")]
             Self::Ssl(stream) => {
                 let pinned = Pin::new(stream);
@@ -62,6 +101,10 @@ impl AsyncWrite for ConnectionStream {
                 let pinned = Pin::new(stream);
                 pinned.poll_write(cx, buf)
             }
+            Self::Custom(stream) => {
+                let pinned = Pin::new(stream);
+                pinned.poll_write(cx, buf)
+            }
             #[cfg(feature = "async-ssl")]
             Self::Ssl(stream) => {
                 let pinned = Pin::new(stream);
@@ -76,6 +119,10 @@ impl AsyncWrite for ConnectionStream {
                 let pinned = Pin::new(stream);
                 pinned.poll_flush(cx)
             }
+            Self::Custom(stream) => {
+                let pinned = Pin::new(stream);
+                pinned.poll_flush(cx)
+            }
             #[cfg(feature = "async-ssl")]
             Self::Ssl(stream) => {
                 let pinned = Pin::new(stream);
@@ -90,6 +137,10 @@ impl AsyncWrite for ConnectionStream {
                 let pinned = Pin::new(stream);
                 pinned.poll_shutdown(cx)
             }
+            Self::Custom(stream) => {
+                let pinned = Pin::new(stream);
+                pinned.poll_shutdown(cx)
+            }
             #[cfg(feature = "async-ssl")]
             Self::Ssl(stream) => {
                 let pinned = Pin::new(stream);
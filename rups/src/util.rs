@@ -29,35 +29,50 @@ impl<'a> Default for UpsdName<'a> {
     }
 }
 
-impl<'a> TryFrom<&'a str> for UpsdName<'a> {
-    type Error = crate::ClientError;
+impl<'a> UpsdName<'a> {
+    /// Parses a `[upsname][@<hostname>[:<port>]]` string, using the given port
+    /// when none is specified (instead of [`DEFAULT_PORT`]).
+    ///
+    /// IPv6 hostnames may be enclosed in brackets (e.g. `ups@[fe80::1%eth0]:3493`), and must
+    /// be when a port is specified.
+    pub fn parse_with_default_port(value: &'a str, default_port: u16) -> crate::Result<Self> {
+        let (upsname, host_port) = match value.split_once('@') {
+            Some((upsname, host_port)) => (Some(upsname), Some(host_port)),
+            None if value.contains(':') => (None, Some(value)),
+            None => (Some(value), None),
+        };
 
-    fn try_from(value: &'a str) -> crate::Result<UpsdName<'a>> {
-        let mut upsname: Option<&str> = None;
         let mut hostname = DEFAULT_HOSTNAME;
-        let mut port = DEFAULT_PORT;
-
-        if value.contains(':') {
-            let mut split = value.splitn(2, ':');
-            let prefix = split.next().unwrap();
-            port = split
-                .next()
-                .unwrap()
-                .parse::<u16>()
-                .map_err(|_| crate::ClientError::generic("Invalid port number"))?;
-            if prefix.contains('@') {
-                let mut split = prefix.splitn(2, '@');
-                upsname = Some(split.next().unwrap());
-                hostname = split.next().unwrap();
+        let mut port = default_port;
+        if let Some(host_port) = host_port {
+            let port_str = if let Some(bracketed) = host_port.strip_prefix('[') {
+                let (host, rest) = bracketed
+                    .split_once(']')
+                    .ok_or_else(|| crate::ClientError::generic("Unterminated IPv6 hostname"))?;
+                hostname = host;
+                if rest.is_empty() {
+                    None
+                } else {
+                    Some(rest.strip_prefix(':').ok_or_else(|| {
+                        crate::ClientError::generic("Unexpected characters after IPv6 hostname")
+                    })?)
+                }
+            } else if host_port.matches(':').count() > 1 {
+                // Bare IPv6 address, without port
+                hostname = host_port;
+                None
+            } else if let Some((host, port_str)) = host_port.split_once(':') {
+                hostname = host;
+                Some(port_str)
             } else {
-                hostname = prefix;
+                hostname = host_port;
+                None
+            };
+            if let Some(port_str) = port_str {
+                port = port_str
+                    .parse::<u16>()
+                    .map_err(|_| crate::ClientError::generic("Invalid port number"))?;
             }
-        } else if value.contains('@') {
-            let mut split = value.splitn(2, '@');
-            upsname = Some(split.next().unwrap());
-            hostname = split.next().unwrap();
-        } else {
-            upsname = Some(value);
         }
 
         Ok(UpsdName {
@@ -68,6 +83,14 @@ impl<'a> TryFrom<&'a str> for UpsdName<'a> {
     }
 }
 
+impl<'a> TryFrom<&'a str> for UpsdName<'a> {
+    type Error = crate::ClientError;
+
+    fn try_from(value: &'a str) -> crate::Result<UpsdName<'a>> {
+        UpsdName::parse_with_default_port(value, DEFAULT_PORT)
+    }
+}
+
 impl<'a> TryInto<crate::Host> for UpsdName<'a> {
     type Error = crate::ClientError;
 
@@ -83,7 +106,168 @@ impl<'a> fmt::Display for UpsdName<'a> {
         if let Some(upsname) = self.upsname {
             write!(f, "{}@", upsname)?;
         }
-        write!(f, "{}:{}", self.hostname, self.port)
+        if self.hostname.contains(':') {
+            write!(f, "[{}]:{}", self.hostname, self.port)
+        } else {
+            write!(f, "{}:{}", self.hostname, self.port)
+        }
+    }
+}
+
+/// A upsd server version, parsed from the version banner (e.g. `Network UPS Tools upsd 2.8.0`).
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ServerVersion {
+    /// The product name preceding the version number, if any.
+    pub product: Option<String>,
+    /// The semantic version as (major, minor, patch), if it could be parsed.
+    pub version: Option<(u16, u16, u16)>,
+    /// The version banner, as sent by the server.
+    pub raw: String,
+}
+
+impl ServerVersion {
+    /// Parses a version banner. Unrecognized banners are kept in `raw`, with no
+    /// product or version.
+    pub fn parse<T: Into<String>>(raw: T) -> Self {
+        let raw = raw.into();
+        let words: Vec<&str> = raw.split_whitespace().collect();
+        let found = words
+            .iter()
+            .enumerate()
+            .find_map(|(i, word)| Self::parse_semver(word).map(|version| (i, version)));
+
+        match found {
+            Some((i, version)) => ServerVersion {
+                product: Some(words[..i].join(" ")).filter(|p| !p.is_empty()),
+                version: Some(version),
+                raw,
+            },
+            None => ServerVersion {
+                product: None,
+                version: None,
+                raw,
+            },
+        }
+    }
+
+    /// Whether the server version is known to be at least the given (major, minor, patch).
+    pub fn is_at_least(&self, version: (u16, u16, u16)) -> bool {
+        self.version.is_some_and(|v| v >= version)
+    }
+
+    /// Parses a word such as `2.8.0`, `2.7.4.1` or `v2.8.0-rc1`.
+    fn parse_semver(word: &str) -> Option<(u16, u16, u16)> {
+        let word = word.strip_prefix('v').unwrap_or(word);
+        if !word.starts_with(|c: char| c.is_ascii_digit()) || !word.contains('.') {
+            return None;
+        }
+        let mut parts = word.split('.').map(|part| {
+            let digits: String = part.chars().take_while(char::is_ascii_digit).collect();
+            digits.parse::<u16>().ok()
+        });
+        let major = parts.next()??;
+        let minor = parts.next()??;
+        let patch = parts.next().flatten().unwrap_or(0);
+        Some((major, minor, patch))
+    }
+}
+
+impl From<String> for ServerVersion {
+    fn from(raw: String) -> Self {
+        ServerVersion::parse(raw)
+    }
+}
+
+impl fmt::Display for ServerVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.raw)
+    }
+}
+
+/// The protocol features supported by a upsd server, as far as they can be told from its
+/// version and `HELP` output.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Capabilities {
+    /// The commands listed by `HELP` (which upsd doesn't keep complete, e.g. `PRIMARY`).
+    pub commands: Vec<String>,
+    /// Whether `PRIMARY` is supported (NUT 2.8+); otherwise, `MASTER` must be used.
+    pub supports_primary: bool,
+    /// Whether the results of `SET` and `INSTCMD` can be tracked (NUT 2.8+).
+    pub supports_tracking: bool,
+    /// Whether `LIST RANGE` is supported (network protocol 1.2+).
+    pub supports_range: bool,
+    /// Whether `STARTTLS` is supported.
+    pub supports_starttls: bool,
+}
+
+impl Capabilities {
+    /// Derives the capabilities from the `HELP` output, the server version and the network
+    /// protocol version (if known).
+    pub(crate) fn new(
+        commands: Vec<String>,
+        version: &ServerVersion,
+        network_version: Option<&str>,
+    ) -> Self {
+        let is_listed = |command: &str| commands.iter().any(|c| c == command);
+        let supports_primary = is_listed("PRIMARY") || version.is_at_least((2, 8, 0));
+        let supports_tracking = is_listed("TRACKING") || version.is_at_least((2, 8, 0));
+        let supports_range = network_version
+            .map(|v| ServerVersion::parse(v).is_at_least((1, 2, 0)))
+            .unwrap_or(false);
+        let supports_starttls = is_listed("STARTTLS");
+        Capabilities {
+            commands,
+            supports_primary,
+            supports_tracking,
+            supports_range,
+            supports_starttls,
+        }
+    }
+
+    /// Parses the `HELP` output (e.g. `Commands: HELP VER GET LIST`) into command names.
+    pub(crate) fn parse_help(help: &str) -> Vec<String> {
+        let help = help.strip_prefix("Commands:").unwrap_or(help);
+        help.split_whitespace().map(String::from).collect()
+    }
+}
+
+/// Whether `name` matches a simple glob `pattern`, where `*` matches any sequence of
+/// characters (including none). Other characters match literally.
+pub(crate) fn glob_matches(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    // Position of the last `*` in the pattern, and of the name when it was reached
+    let mut star: Option<(usize, usize)> = None;
+
+    while n < name.len() {
+        if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, n));
+            p += 1;
+        } else if p < pattern.len() && pattern[p] == name[n] {
+            p += 1;
+            n += 1;
+        } else if let Some((star_p, star_n)) = star {
+            // Let the last `*` match one more character, and retry from there
+            star = Some((star_p, star_n + 1));
+            p = star_p + 1;
+            n = star_n + 1;
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// Whether a client address, as listed by `LIST CLIENT`, is a loopback address
+/// (including IPv4-mapped IPv6 addresses such as `::ffff:127.0.0.1`).
+pub(crate) fn is_loopback_client(addr: &str) -> bool {
+    match addr.parse::<std::net::IpAddr>() {
+        Ok(std::net::IpAddr::V6(ip)) => {
+            ip.is_loopback() || ip.to_ipv4_mapped().is_some_and(|ip| ip.is_loopback())
+        }
+        Ok(ip) => ip.is_loopback(),
+        Err(_) => false,
     }
 }
 
@@ -147,4 +331,112 @@ mod tests {
         );
         assert_eq!(format!("{}", name), "ups@notlocal:3493");
     }
+
+    #[test]
+    fn test_upsdname_parser_default_port() {
+        let name = UpsdName::parse_with_default_port("ups@notlocal", 4000).unwrap();
+        assert_eq!(
+            name,
+            UpsdName {
+                upsname: Some("ups"),
+                hostname: "notlocal",
+                port: 4000
+            }
+        );
+
+        let name = UpsdName::parse_with_default_port("ups@notlocal:1234", 4000).unwrap();
+        assert_eq!(name.port, 1234);
+    }
+
+    #[test]
+    fn test_upsdname_parser_ipv6() {
+        let name: UpsdName = "ups@[fe80::1%eth0]:1234".try_into().unwrap();
+        assert_eq!(
+            name,
+            UpsdName {
+                upsname: Some("ups"),
+                hostname: "fe80::1%eth0",
+                port: 1234
+            }
+        );
+        assert_eq!(name.to_string(), "ups@[fe80::1%eth0]:1234");
+
+        let name: UpsdName = "ups@::1".try_into().unwrap();
+        assert_eq!(name.hostname, "::1");
+        assert_eq!(name.port, DEFAULT_PORT);
+
+        let name: UpsdName = "[::1]".try_into().unwrap();
+        assert_eq!(name.upsname, None);
+        assert_eq!(name.hostname, "::1");
+
+        assert!(UpsdName::try_from("ups@[::1").is_err());
+        assert!(UpsdName::try_from("ups@[::1]1234").is_err());
+    }
+
+    #[test]
+    fn test_glob_matches() {
+        assert!(glob_matches("battery.*", "battery.charge"));
+        assert!(glob_matches("battery.*", "battery.charge.low"));
+        assert!(!glob_matches("battery.*", "ups.battery.charge"));
+        assert!(glob_matches("*.voltage", "input.voltage"));
+        assert!(glob_matches("*.voltage*", "output.voltage.nominal"));
+        assert!(glob_matches("*", ""));
+        assert!(glob_matches("ups.status", "ups.status"));
+        assert!(!glob_matches("ups.status", "ups.statuses"));
+        assert!(glob_matches("a*b*c", "aXbYbZc"));
+        assert!(!glob_matches("a*b*c", "aXbYbZ"));
+    }
+
+    #[test]
+    fn test_is_loopback_client() {
+        assert!(is_loopback_client("127.0.0.1"));
+        assert!(is_loopback_client("::1"));
+        assert!(is_loopback_client("::ffff:127.0.0.1"));
+        assert!(!is_loopback_client("192.168.1.10"));
+        assert!(!is_loopback_client("localhost"));
+    }
+
+    #[test]
+    fn test_capabilities() {
+        let commands = Capabilities::parse_help(
+            "Commands: HELP VER GET LIST SET INSTCMD LOGIN LOGOUT USERNAME PASSWORD STARTTLS",
+        );
+        assert_eq!(commands.len(), 11);
+
+        let version = ServerVersion::parse("Network UPS Tools upsd 2.8.0");
+        let caps = Capabilities::new(commands.clone(), &version, Some("1.3"));
+        assert!(caps.supports_primary);
+        assert!(caps.supports_tracking);
+        assert!(caps.supports_range);
+        assert!(caps.supports_starttls);
+
+        let version = ServerVersion::parse("Network UPS Tools upsd 2.7.4");
+        let caps = Capabilities::new(commands, &version, None);
+        assert!(!caps.supports_primary);
+        assert!(!caps.supports_tracking);
+        assert!(!caps.supports_range);
+    }
+
+    #[test]
+    fn test_server_version_parser() {
+        let version =
+            ServerVersion::parse("Network UPS Tools upsd 2.8.0 - http://www.networkupstools.org/");
+        assert_eq!(version.product.as_deref(), Some("Network UPS Tools upsd"));
+        assert_eq!(version.version, Some((2, 8, 0)));
+        assert!(version.is_at_least((2, 7, 4)));
+        assert!(!version.is_at_least((2, 8, 1)));
+
+        let version = ServerVersion::parse("Network UPS Tools upsd 2.7.4.1");
+        assert_eq!(version.version, Some((2, 7, 4)));
+
+        let version = ServerVersion::parse("upsd v2.8-rc1");
+        assert_eq!(version.product.as_deref(), Some("upsd"));
+        assert_eq!(version.version, Some((2, 8, 0)));
+
+        let version = ServerVersion::parse("some custom server");
+        assert_eq!(version.product, None);
+        assert_eq!(version.version, None);
+        assert_eq!(version.raw, "some custom server");
+        assert!(!version.is_at_least((0, 0, 0)));
+    }
 }
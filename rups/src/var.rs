@@ -1,5 +1,6 @@
 use core::fmt;
-use std::collections::HashSet;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
 use std::convert::TryFrom;
 use std::time::Duration;
 
@@ -27,6 +28,20 @@ pub mod key {
     pub const DEVICE_MAC_ADDRESS: &str = "device.macaddr";
     /// Device uptime.
     pub const DEVICE_UPTIME: &str = "device.uptime";
+    /// UPS status.
+    pub const UPS_STATUS: &str = "ups.status";
+    /// UPS alarms, when the status includes `ALARM`.
+    pub const UPS_ALARM: &str = "ups.alarm";
+    /// UPS load (percent).
+    pub const UPS_LOAD: &str = "ups.load";
+    /// Battery charge (percent).
+    pub const BATTERY_CHARGE: &str = "battery.charge";
+    /// Input voltage (V).
+    pub const INPUT_VOLTAGE: &str = "input.voltage";
+    /// UPS temperature (degrees C).
+    pub const UPS_TEMPERATURE: &str = "ups.temperature";
+    /// Battery temperature (degrees C).
+    pub const BATTERY_TEMPERATURE: &str = "battery.temperature";
 }
 
 /// Well-known variables for NUT UPS devices.
@@ -54,6 +69,10 @@ pub enum Variable {
     DeviceMacAddress(String),
     /// Device uptime.
     DeviceUptime(Duration),
+    /// UPS temperature.
+    UpsTemperature(Temperature),
+    /// Battery temperature.
+    BatteryTemperature(Temperature),
 
     /// Any other variable. Value is a tuple of (key, value).
     Other((String, String)),
@@ -74,9 +93,18 @@ impl Variable {
             DEVICE_LOCATION => Self::DeviceLocation(value),
             DEVICE_PART => Self::DevicePart(value),
             DEVICE_MAC_ADDRESS => Self::DeviceMacAddress(value),
-            DEVICE_UPTIME => Self::DeviceUptime(Duration::from_secs(
-                value.parse().expect("invalid uptime value"),
-            )),
+            DEVICE_UPTIME => match value.parse::<u64>() {
+                // Only keep the typed value if it can be written back as-is
+                Ok(secs) if secs.to_string() == value => {
+                    Self::DeviceUptime(Duration::from_secs(secs))
+                }
+                _ => Self::Other((name.into(), value)),
+            },
+            UPS_TEMPERATURE | BATTERY_TEMPERATURE => match Temperature::parse(&value) {
+                Some(temp) if name == UPS_TEMPERATURE => Self::UpsTemperature(temp),
+                Some(temp) => Self::BatteryTemperature(temp),
+                None => Self::Other((name.into(), value)),
+            },
 
             _ => Self::Other((name.into(), value)),
         }
@@ -96,26 +124,59 @@ impl Variable {
             Self::DevicePart(_) => DEVICE_PART,
             Self::DeviceMacAddress(_) => DEVICE_MAC_ADDRESS,
             Self::DeviceUptime(_) => DEVICE_UPTIME,
+            Self::UpsTemperature(_) => UPS_TEMPERATURE,
+            Self::BatteryTemperature(_) => BATTERY_TEMPERATURE,
             Self::Other((name, _)) => name.as_str(),
         }
     }
 
     /// Returns the value of the NUT variable.
     pub fn value(&self) -> String {
+        self.raw().into_owned()
+    }
+
+    /// Returns the value of the NUT variable, exactly as it was sent by the server.
+    pub fn raw(&self) -> Cow<'_, str> {
         match self {
-            Self::DeviceModel(value) => value.clone(),
-            Self::DeviceManufacturer(value) => value.clone(),
-            Self::DeviceSerial(value) => value.clone(),
-            Self::DeviceType(value) => value.to_string(),
-            Self::DeviceDescription(value) => value.clone(),
-            Self::DeviceContact(value) => value.clone(),
-            Self::DeviceLocation(value) => value.clone(),
-            Self::DevicePart(value) => value.clone(),
-            Self::DeviceMacAddress(value) => value.clone(),
-            Self::DeviceUptime(value) => value.as_secs().to_string(),
-            Self::Other((_, value)) => value.clone(),
+            Self::DeviceModel(value) => Cow::Borrowed(value),
+            Self::DeviceManufacturer(value) => Cow::Borrowed(value),
+            Self::DeviceSerial(value) => Cow::Borrowed(value),
+            Self::DeviceType(value) => Cow::Borrowed(value.as_str()),
+            Self::DeviceDescription(value) => Cow::Borrowed(value),
+            Self::DeviceContact(value) => Cow::Borrowed(value),
+            Self::DeviceLocation(value) => Cow::Borrowed(value),
+            Self::DevicePart(value) => Cow::Borrowed(value),
+            Self::DeviceMacAddress(value) => Cow::Borrowed(value),
+            Self::DeviceUptime(value) => Cow::Owned(value.as_secs().to_string()),
+            Self::UpsTemperature(value) => Cow::Borrowed(value.raw()),
+            Self::BatteryTemperature(value) => Cow::Borrowed(value.raw()),
+            Self::Other((_, value)) => Cow::Borrowed(value),
         }
     }
+
+    /// Returns the value of the NUT variable as a number, if it is numeric.
+    pub fn as_f64(&self) -> Option<f64> {
+        self.value()
+            .trim()
+            .parse::<f64>()
+            .ok()
+            .filter(|value| value.is_finite())
+    }
+
+    /// Parses the value of the NUT variable into any type implementing [`FromStr`](std::str::FromStr)
+    /// (e.g. `u32`, `f64`, `bool`), ignoring surrounding whitespace.
+    ///
+    /// Parse failures are reported as [`NutError::ParseValue`](crate::NutError::ParseValue).
+    pub fn parse_value<T: std::str::FromStr>(&self) -> crate::Result<T>
+    where
+        T::Err: fmt::Display,
+    {
+        let raw = self.raw();
+        raw.trim().parse::<T>().map_err(|e| {
+            crate::NutError::ParseValue(format!("{} = {:?} ({})", self.name(), raw.as_ref(), e))
+                .into()
+        })
+    }
 }
 
 impl fmt::Display for Variable {
@@ -153,6 +214,18 @@ impl DeviceType {
             _ => Self::Other(v),
         }
     }
+
+    /// Returns the NUT name of the device type.
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Ups => "ups",
+            Self::Pdu => "pdu",
+            Self::Scd => "scd",
+            Self::Psu => "psu",
+            Self::Ats => "ats",
+            Self::Other(val) => val.as_str(),
+        }
+    }
 }
 
 impl fmt::Display for DeviceType {
@@ -168,10 +241,187 @@ impl fmt::Display for DeviceType {
     }
 }
 
+/// A temperature, as reported by NUT (in degrees Celsius).
+#[derive(Debug, Clone)]
+pub struct Temperature {
+    celsius: f64,
+    raw: String,
+}
+
+impl Temperature {
+    /// Creates a temperature from degrees Celsius.
+    pub fn from_celsius(celsius: f64) -> Self {
+        Self {
+            celsius,
+            raw: celsius.to_string(),
+        }
+    }
+
+    /// Parses a temperature in degrees Celsius.
+    ///
+    /// Surrounding whitespace and a trailing unit (e.g. `C` or `°C`) are ignored.
+    /// Returns `None` if the value is not a finite number.
+    pub fn parse(value: &str) -> Option<Self> {
+        value
+            .trim()
+            .trim_end_matches(|c: char| c.is_alphabetic() || c == '°')
+            .trim_end()
+            .parse::<f64>()
+            .ok()
+            .filter(|celsius| celsius.is_finite())
+            .map(|celsius| Self {
+                celsius,
+                raw: value.to_owned(),
+            })
+    }
+
+    /// Returns the temperature in degrees Celsius.
+    pub fn as_celsius(&self) -> f64 {
+        self.celsius
+    }
+
+    /// Returns the temperature in degrees Fahrenheit.
+    pub fn as_fahrenheit(&self) -> f64 {
+        self.celsius * 9.0 / 5.0 + 32.0
+    }
+
+    /// Returns the temperature exactly as it was parsed.
+    pub fn raw(&self) -> &str {
+        &self.raw
+    }
+}
+
+impl PartialEq for Temperature {
+    fn eq(&self, other: &Self) -> bool {
+        self.celsius.to_bits() == other.celsius.to_bits() && self.raw == other.raw
+    }
+}
+
+impl Eq for Temperature {}
+
+impl fmt::Display for Temperature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.celsius)
+    }
+}
+
+/// A flag of the UPS status (`ups.status`).
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum UpsStatusFlag {
+    /// On line, mains is present (`OL`).
+    OnLine,
+    /// On battery, mains is not present (`OB`).
+    OnBattery,
+    /// Low battery (`LB`).
+    LowBattery,
+    /// High battery (`HB`).
+    HighBattery,
+    /// The battery needs to be replaced (`RB`).
+    ReplaceBattery,
+    /// The battery is charging (`CHRG`).
+    Charging,
+    /// The battery is discharging (`DISCHRG`).
+    Discharging,
+    /// The UPS is on bypass (`BYPASS`).
+    Bypass,
+    /// The UPS is calibrating its battery (`CAL`).
+    Calibrating,
+    /// The UPS output is off (`OFF`).
+    Off,
+    /// The UPS is overloaded (`OVER`).
+    Overloaded,
+    /// The UPS is trimming incoming voltage (`TRIM`).
+    Trimming,
+    /// The UPS is boosting incoming voltage (`BOOST`).
+    Boosting,
+    /// A forced shutdown is in progress (`FSD`).
+    ForcedShutdown,
+    /// The UPS has an active alarm, see `ups.alarm` (`ALARM`).
+    Alarm,
+    /// Any other status flag.
+    Other(String),
+}
+
+impl UpsStatusFlag {
+    /// Parses a single status flag.
+    pub fn parse(flag: &str) -> UpsStatusFlag {
+        match flag {
+            "OL" => Self::OnLine,
+            "OB" => Self::OnBattery,
+            "LB" => Self::LowBattery,
+            "HB" => Self::HighBattery,
+            "RB" => Self::ReplaceBattery,
+            "CHRG" => Self::Charging,
+            "DISCHRG" => Self::Discharging,
+            "BYPASS" => Self::Bypass,
+            "CAL" => Self::Calibrating,
+            "OFF" => Self::Off,
+            "OVER" => Self::Overloaded,
+            "TRIM" => Self::Trimming,
+            "BOOST" => Self::Boosting,
+            "FSD" => Self::ForcedShutdown,
+            "ALARM" => Self::Alarm,
+            _ => Self::Other(flag.into()),
+        }
+    }
+
+    /// Parses the value of `ups.status`, which is a space-separated list of flags.
+    pub fn parse_status(status: &str) -> HashSet<UpsStatusFlag> {
+        status.split_whitespace().map(Self::parse).collect()
+    }
+
+    /// Returns the NUT name of the flag.
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::OnLine => "OL",
+            Self::OnBattery => "OB",
+            Self::LowBattery => "LB",
+            Self::HighBattery => "HB",
+            Self::ReplaceBattery => "RB",
+            Self::Charging => "CHRG",
+            Self::Discharging => "DISCHRG",
+            Self::Bypass => "BYPASS",
+            Self::Calibrating => "CAL",
+            Self::Off => "OFF",
+            Self::Overloaded => "OVER",
+            Self::Trimming => "TRIM",
+            Self::Boosting => "BOOST",
+            Self::ForcedShutdown => "FSD",
+            Self::Alarm => "ALARM",
+            Self::Other(flag) => flag.as_str(),
+        }
+    }
+}
+
+/// Splits the value of `ups.alarm` into individual alarms.
+///
+/// Drivers from NUT 2.8 enclose each alarm in brackets (e.g. `[Replace battery!] [Overload!]`);
+/// otherwise, the whole value is a single alarm.
+pub(crate) fn parse_alarms(value: &str) -> Vec<String> {
+    let value = value.trim();
+    if value.is_empty() {
+        return Vec::new();
+    }
+    if !value.starts_with('[') {
+        return vec![value.to_string()];
+    }
+    value
+        .split(['[', ']'])
+        .map(str::trim)
+        .filter(|alarm| !alarm.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+impl fmt::Display for UpsStatusFlag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
 /// NUT Variable type
-#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
-#[allow(dead_code)]
-pub(crate) enum VariableType {
+#[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum VariableType {
     /// A mutable variable (`RW`).
     Rw,
     /// An enumerated type, which supports a few specific values (`ENUM`).
@@ -182,6 +432,21 @@ pub(crate) enum VariableType {
     Range,
     /// A simple numeric value, either integer or float.
     Number,
+    /// A type not recognized by this client, kept as sent by the server.
+    Unknown(String),
+}
+
+impl fmt::Display for VariableType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Rw => write!(f, "RW"),
+            Self::Enum => write!(f, "ENUM"),
+            Self::String(size) => write!(f, "STRING:{}", size),
+            Self::Range => write!(f, "RANGE"),
+            Self::Number => write!(f, "NUMBER"),
+            Self::Unknown(raw) => write!(f, "{}", raw),
+        }
+    }
 }
 
 impl TryFrom<&str> for VariableType {
@@ -201,10 +466,7 @@ impl TryFrom<&str> for VariableType {
                         .ok_or_else(|| crate::ClientError::generic("Invalid STRING definition"))?;
                     Ok(Self::String(size))
                 } else {
-                    Err(crate::ClientError::generic(format!(
-                        "Unrecognized variable type: {}",
-                        value
-                    )))
+                    Ok(Self::Unknown(other.to_owned()))
                 }
             }
         }
@@ -247,6 +509,19 @@ impl VariableDefinition {
         self.1.contains(&VariableType::Number)
     }
 
+    /// Returns the types of this variable, in a stable order.
+    pub fn types(&self) -> Vec<VariableType> {
+        let mut types: Vec<VariableType> = self.1.iter().cloned().collect();
+        types.sort();
+        types
+    }
+
+    /// Returns the NUT names of the types of this variable (e.g. `RW`, `STRING:32`),
+    /// in the same order as [`VariableDefinition::types`].
+    pub fn type_names(&self) -> Vec<String> {
+        self.types().iter().map(ToString::to_string).collect()
+    }
+
     /// Returns the max string length, if applicable.
     pub fn get_string_length(&self) -> Option<usize> {
         self.1.iter().find_map(|t| match t {
@@ -273,14 +548,382 @@ impl<A: ToString> TryFrom<(A, Vec<&str>)> for VariableDefinition {
 
 /// A range of values for a variable.
 #[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct VariableRange(pub String, pub String);
 
+/// A mutable variable, along with its type definition and allowed values.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct EditableVar {
+    /// The name of the variable.
+    pub name: String,
+    /// The current value of the variable.
+    pub value: String,
+    /// The type definition of the variable.
+    pub definition: VariableDefinition,
+    /// The allowed values, if the variable is an enumerated type.
+    pub enum_values: Vec<String>,
+    /// The allowed ranges, if the variable is a numeric range type.
+    pub ranges: Vec<VariableRange>,
+}
+
+/// The constraints on the values of a variable: its type, and its allowed ranges or values.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct VarConstraints {
+    /// The type definition of the variable.
+    pub definition: VariableDefinition,
+    /// The allowed ranges, if the variable is a numeric range type.
+    pub ranges: Vec<VariableRange>,
+    /// The allowed values, if the variable is an enumerated type.
+    pub enums: Vec<String>,
+}
+
+/// The complete schema of a UPS device: its variables and commands.
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct DeviceSchema {
+    /// The name of the device.
+    pub ups_name: String,
+    /// The variables of the device.
+    pub variables: Vec<VariableSchema>,
+    /// The instant commands of the device.
+    pub commands: Vec<CommandSchema>,
+}
+
+/// The schema of a variable, as part of a [`DeviceSchema`].
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct VariableSchema {
+    /// The name of the variable.
+    pub name: String,
+    /// The current value of the variable.
+    pub value: String,
+    /// The description of the variable.
+    pub description: String,
+    /// The NUT names of the variable types (e.g. `RW`, `STRING:32`).
+    pub types: Vec<String>,
+    /// The allowed values, if the variable is an enumerated type.
+    pub enum_values: Vec<String>,
+    /// The allowed ranges, if the variable is a numeric range type.
+    pub ranges: Vec<VariableRange>,
+}
+
+impl VariableSchema {
+    /// Whether this variable is mutable.
+    pub fn is_mutable(&self) -> bool {
+        self.types.iter().any(|t| t == "RW")
+    }
+}
+
+/// The schema of an instant command, as part of a [`DeviceSchema`].
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct CommandSchema {
+    /// The name of the command.
+    pub name: String,
+    /// The description of the command.
+    pub description: String,
+}
+
+/// The state of a whole NUT server: its versions, and every UPS device it serves.
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Report {
+    /// The server version.
+    pub server_version: String,
+    /// The network protocol version.
+    pub network_version: String,
+    /// The UPS devices.
+    pub devices: Vec<DeviceReport>,
+}
+
+/// The state of a UPS device, as part of a [`Report`].
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct DeviceReport {
+    /// The description of the device.
+    pub description: String,
+    /// The number of clients logged in to the device.
+    pub num_logins: i32,
+    /// The variables and commands of the device (including its name).
+    pub schema: DeviceSchema,
+}
+
+/// The outcome of setting several variables in sequence (see `Connection::set_vars`).
+///
+/// Variables are listed in the order they were given.
+#[cfg(feature = "write")]
+#[derive(Debug, Default)]
+pub struct SetReport {
+    /// The variables that were set.
+    pub applied: Vec<String>,
+    /// The variables that could not be set, with the reason.
+    pub failed: Vec<(String, crate::ClientError)>,
+    /// The variables that were not attempted, because the connection failed before.
+    pub skipped: Vec<String>,
+}
+
+#[cfg(feature = "write")]
+impl SetReport {
+    /// Whether every variable was set.
+    pub fn is_complete(&self) -> bool {
+        self.failed.is_empty() && self.skipped.is_empty()
+    }
+}
+
+/// The confirmation required to set the forced shutdown flag on a UPS, so that it can't
+/// be done by accident (see `Connection::fsd_with_confirmation`).
+#[cfg(feature = "write")]
+#[derive(Debug)]
+pub struct FsdConfirm {
+    _private: (),
+}
+
+#[cfg(feature = "write")]
+impl FsdConfirm {
+    /// Confirms that the UPS must really be shut down, along with the systems it powers.
+    pub fn yes_really() -> Self {
+        FsdConfirm { _private: () }
+    }
+}
+
+/// The variables of a UPS device, as queried at one point in time.
+///
+/// Variables can be looked up by name, either with [`DeviceSnapshot::get`] or by indexing
+/// (which panics if the variable is missing), and iterated in the order sent by the server.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct DeviceSnapshot {
+    vars: Vec<Variable>,
+    index: HashMap<String, usize>,
+}
+
+impl DeviceSnapshot {
+    /// Returns the variable with the given name, if present.
+    pub fn get(&self, name: &str) -> Option<&Variable> {
+        self.index.get(name).map(|i| &self.vars[*i])
+    }
+
+    /// Iterates over the variables.
+    pub fn iter(&self) -> std::slice::Iter<'_, Variable> {
+        self.vars.iter()
+    }
+
+    /// The number of variables.
+    pub fn len(&self) -> usize {
+        self.vars.len()
+    }
+
+    /// Whether there are no variables.
+    pub fn is_empty(&self) -> bool {
+        self.vars.is_empty()
+    }
+}
+
+impl FromIterator<Variable> for DeviceSnapshot {
+    fn from_iter<T: IntoIterator<Item = Variable>>(iter: T) -> Self {
+        let mut snapshot = Self::default();
+        for var in iter {
+            match snapshot.index.get(var.name()) {
+                Some(i) => snapshot.vars[*i] = var,
+                None => {
+                    snapshot
+                        .index
+                        .insert(var.name().to_owned(), snapshot.vars.len());
+                    snapshot.vars.push(var);
+                }
+            }
+        }
+        snapshot
+    }
+}
+
+impl std::ops::Index<&str> for DeviceSnapshot {
+    type Output = Variable;
+
+    fn index(&self, name: &str) -> &Variable {
+        self.get(name)
+            .unwrap_or_else(|| panic!("Variable not found in snapshot: {}", name))
+    }
+}
+
+impl IntoIterator for DeviceSnapshot {
+    type Item = Variable;
+    type IntoIter = std::vec::IntoIter<Variable>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.vars.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a DeviceSnapshot {
+    type Item = &'a Variable;
+    type IntoIter = std::slice::Iter<'a, Variable>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.vars.iter()
+    }
+}
+
+/// A difference of a variable between two lists of variables, see [`diff`].
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum VariableDelta {
+    /// The variable is only in the new list.
+    Added(Variable),
+    /// The variable is only in the old list.
+    Removed(Variable),
+    /// The variable is in both lists, with a different value.
+    Changed {
+        /// The variable in the old list.
+        old: Variable,
+        /// The variable in the new list.
+        new: Variable,
+    },
+}
+
+impl VariableDelta {
+    /// The name of the variable that changed.
+    pub fn name(&self) -> &str {
+        match self {
+            Self::Added(var) | Self::Removed(var) => var.name(),
+            Self::Changed { new, .. } => new.name(),
+        }
+    }
+}
+
+/// Compares two lists of variables (e.g. from two polls of the same device) by name,
+/// returning the variables that were added, removed, or changed, sorted by name.
+///
+/// The order of the variables in each list doesn't matter.
+pub fn diff(old: &[Variable], new: &[Variable]) -> Vec<VariableDelta> {
+    let old_vars: HashMap<&str, &Variable> = old.iter().map(|var| (var.name(), var)).collect();
+    let new_vars: HashMap<&str, &Variable> = new.iter().map(|var| (var.name(), var)).collect();
+
+    let mut deltas = Vec::new();
+    for (name, old_var) in &old_vars {
+        match new_vars.get(name) {
+            None => deltas.push(VariableDelta::Removed((*old_var).clone())),
+            Some(new_var) if old_var.raw() != new_var.raw() => {
+                deltas.push(VariableDelta::Changed {
+                    old: (*old_var).clone(),
+                    new: (*new_var).clone(),
+                })
+            }
+            Some(_) => {}
+        }
+    }
+    for (name, new_var) in &new_vars {
+        if !old_vars.contains_key(name) {
+            deltas.push(VariableDelta::Added((*new_var).clone()));
+        }
+    }
+    deltas.sort_by(|a, b| a.name().cmp(b.name()));
+    deltas
+}
+
+/// The most commonly graphed metrics of a UPS device.
+///
+/// Metrics that are not supported by the device (or are not numeric) are `None`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CommonMetrics {
+    /// The battery charge, in percent (`battery.charge`).
+    pub battery_charge: Option<f64>,
+    /// The UPS load, in percent (`ups.load`).
+    pub ups_load: Option<f64>,
+    /// The input voltage, in volts (`input.voltage`).
+    pub input_voltage: Option<f64>,
+    /// The UPS status flags (`ups.status`), empty if not supported.
+    pub status: HashSet<UpsStatusFlag>,
+}
+
+impl CommonMetrics {
+    /// The variables queried to build the metrics.
+    pub(crate) const KEYS: [&'static str; 4] = [
+        key::BATTERY_CHARGE,
+        key::UPS_LOAD,
+        key::INPUT_VOLTAGE,
+        key::UPS_STATUS,
+    ];
+}
+
+impl FromIterator<Variable> for CommonMetrics {
+    fn from_iter<T: IntoIterator<Item = Variable>>(iter: T) -> Self {
+        let mut metrics = Self::default();
+        for var in iter {
+            match var.name() {
+                key::BATTERY_CHARGE => metrics.battery_charge = var.as_f64(),
+                key::UPS_LOAD => metrics.ups_load = var.as_f64(),
+                key::INPUT_VOLTAGE => metrics.input_voltage = var.as_f64(),
+                key::UPS_STATUS => metrics.status = UpsStatusFlag::parse_status(&var.raw()),
+                _ => {}
+            }
+        }
+        metrics
+    }
+}
+
+/// The identity of a UPS device, as used for inventories.
+///
+/// Fields that are not supported by the device are `None`.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct DeviceIdentity {
+    /// The device model (`device.model`).
+    pub model: Option<String>,
+    /// The device manufacturer (`device.mfr`).
+    pub manufacturer: Option<String>,
+    /// The device serial number (`device.serial`).
+    pub serial: Option<String>,
+    /// The device type (`device.type`), e.g. `ups` or `pdu`.
+    pub device_type: Option<String>,
+    /// The device physical location (`device.location`).
+    pub location: Option<String>,
+}
+
+impl DeviceIdentity {
+    /// The variables queried to build the identity.
+    pub(crate) const KEYS: [&'static str; 5] = [
+        key::DEVICE_MODEL,
+        key::DEVICE_MANUFACTURER,
+        key::DEVICE_SERIAL,
+        key::DEVICE_TYPE,
+        key::DEVICE_LOCATION,
+    ];
+}
+
+impl FromIterator<Variable> for DeviceIdentity {
+    fn from_iter<T: IntoIterator<Item = Variable>>(iter: T) -> Self {
+        let mut identity = Self::default();
+        for var in iter {
+            let value = Some(var.value());
+            match var.name() {
+                key::DEVICE_MODEL => identity.model = value,
+                key::DEVICE_MANUFACTURER => identity.manufacturer = value,
+                key::DEVICE_SERIAL => identity.serial = value,
+                key::DEVICE_TYPE => identity.device_type = value,
+                key::DEVICE_LOCATION => identity.location = value,
+                _ => {}
+            }
+        }
+        identity
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::iter::FromIterator;
 
     use super::*;
 
+    #[test]
+    fn test_parse_alarms() {
+        assert_eq!(
+            parse_alarms("[Replace battery!] [Overload!]"),
+            vec!["Replace battery!", "Overload!"]
+        );
+        assert_eq!(parse_alarms("Replace battery!"), vec!["Replace battery!"]);
+        assert!(parse_alarms("").is_empty());
+        assert!(parse_alarms("  ").is_empty());
+    }
+
     #[test]
     fn test_parse_variable_definition() {
         assert_eq!(
@@ -335,5 +978,187 @@ mod tests {
                 .get_string_length(),
             Some(123)
         );
+        assert_eq!(
+            VariableDefinition::try_from(("var1", vec!["STRING:123", "RW"]))
+                .unwrap()
+                .types(),
+            vec![VariableType::Rw, VariableType::String(123)]
+        );
+        assert_eq!(VariableType::String(64).to_string(), "STRING:64");
+
+        let definition = VariableDefinition::try_from(("var2", vec!["RW", "FUTURE"])).unwrap();
+        assert!(definition.is_mutable());
+        assert_eq!(
+            definition.types(),
+            vec![VariableType::Rw, VariableType::Unknown("FUTURE".into())]
+        );
+        assert_eq!(definition.type_names(), vec!["RW", "FUTURE"]);
+        assert!(VariableDefinition::try_from(("var2", vec!["STRING:abc"])).is_err());
+    }
+
+    #[test]
+    fn test_diff() {
+        let old = vec![
+            Variable::parse("battery.charge", "100".into()),
+            Variable::parse("ups.status", "OL".into()),
+            Variable::parse("ups.load", "20".into()),
+        ];
+        let new = vec![
+            Variable::parse("ups.status", "OB".into()),
+            Variable::parse("input.voltage", "0".into()),
+            Variable::parse("battery.charge", "100".into()),
+        ];
+        assert_eq!(
+            diff(&old, &new),
+            vec![
+                VariableDelta::Added(Variable::parse("input.voltage", "0".into())),
+                VariableDelta::Removed(Variable::parse("ups.load", "20".into())),
+                VariableDelta::Changed {
+                    old: Variable::parse("ups.status", "OL".into()),
+                    new: Variable::parse("ups.status", "OB".into()),
+                },
+            ]
+        );
+
+        let mut shuffled = old.clone();
+        shuffled.reverse();
+        assert!(diff(&old, &shuffled).is_empty());
+    }
+
+    #[test]
+    fn test_common_metrics() {
+        let metrics: CommonMetrics = vec![
+            Variable::parse(key::BATTERY_CHARGE, "87".into()),
+            Variable::parse(key::INPUT_VOLTAGE, "n/a".into()),
+            Variable::parse(key::UPS_STATUS, "OL CHRG".into()),
+            Variable::parse(key::DEVICE_MODEL, "Smart-UPS".into()),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(metrics.battery_charge, Some(87.0));
+        assert_eq!(metrics.ups_load, None);
+        assert_eq!(metrics.input_voltage, None);
+        assert_eq!(
+            metrics.status,
+            HashSet::from_iter(vec![UpsStatusFlag::OnLine, UpsStatusFlag::Charging])
+        );
+    }
+
+    #[test]
+    fn test_parse_ups_status() {
+        assert_eq!(
+            UpsStatusFlag::parse_status("OB DISCHRG LB"),
+            HashSet::from_iter(vec![
+                UpsStatusFlag::OnBattery,
+                UpsStatusFlag::Discharging,
+                UpsStatusFlag::LowBattery
+            ])
+        );
+        assert_eq!(
+            UpsStatusFlag::parse_status("FSD OB LB RB OVER TRIM ALARM ECO"),
+            HashSet::from_iter(vec![
+                UpsStatusFlag::ForcedShutdown,
+                UpsStatusFlag::OnBattery,
+                UpsStatusFlag::LowBattery,
+                UpsStatusFlag::ReplaceBattery,
+                UpsStatusFlag::Overloaded,
+                UpsStatusFlag::Trimming,
+                UpsStatusFlag::Alarm,
+                UpsStatusFlag::Other("ECO".into()),
+            ])
+        );
+        for flag in [
+            "OL", "OB", "LB", "HB", "RB", "CHRG", "DISCHRG", "BYPASS", "CAL", "OFF", "OVER",
+            "TRIM", "BOOST", "FSD", "ALARM",
+        ] {
+            let parsed = UpsStatusFlag::parse(flag);
+            assert!(!matches!(parsed, UpsStatusFlag::Other(_)), "{}", flag);
+            assert_eq!(parsed.as_str(), flag);
+        }
+        assert_eq!(
+            UpsStatusFlag::parse_status(" OL  FOO "),
+            HashSet::from_iter(vec![
+                UpsStatusFlag::OnLine,
+                UpsStatusFlag::Other("FOO".into())
+            ])
+        );
+        assert!(UpsStatusFlag::parse_status("").is_empty());
+        assert_eq!(UpsStatusFlag::Discharging.to_string(), "DISCHRG");
+    }
+
+    #[test]
+    fn test_parse_temperature() {
+        assert_eq!(
+            Variable::parse(key::UPS_TEMPERATURE, "25.5".into()),
+            Variable::UpsTemperature(Temperature::from_celsius(25.5))
+        );
+        let battery = Variable::parse(key::BATTERY_TEMPERATURE, " 30 C ".into());
+        assert!(
+            matches!(&battery, Variable::BatteryTemperature(temp) if temp.as_celsius() == 30.0)
+        );
+        assert_eq!(battery.raw(), " 30 C ");
+        assert_eq!(
+            Variable::parse(key::UPS_TEMPERATURE, "n/a".into()),
+            Variable::Other((key::UPS_TEMPERATURE.into(), "n/a".into()))
+        );
+        assert_eq!(Temperature::parse("-10°C").unwrap().as_celsius(), -10.0);
+        assert_eq!(Temperature::from_celsius(100.0).as_fahrenheit(), 212.0);
+        assert_eq!(Temperature::parse("NaN"), None);
+    }
+
+    #[test]
+    fn test_device_snapshot() {
+        let snapshot: DeviceSnapshot = vec![
+            Variable::parse("battery.charge", "100".into()),
+            Variable::parse(key::DEVICE_MODEL, "Smart-UPS".into()),
+        ]
+        .into_iter()
+        .collect();
+
+        assert_eq!(snapshot.len(), 2);
+        assert_eq!(snapshot["battery.charge"].value(), "100");
+        assert_eq!(
+            snapshot.get(key::DEVICE_MODEL),
+            Some(&Variable::DeviceModel("Smart-UPS".into()))
+        );
+        assert_eq!(snapshot.get("ups.load"), None);
+        assert_eq!(
+            snapshot
+                .into_iter()
+                .map(|var| var.name().to_owned())
+                .collect::<Vec<_>>(),
+            vec!["battery.charge", key::DEVICE_MODEL]
+        );
+    }
+
+    #[test]
+    fn test_variable_parse_value() {
+        let charge = Variable::parse(key::BATTERY_CHARGE, " 87 ".into());
+        assert_eq!(charge.parse_value::<u8>().unwrap(), 87);
+        assert_eq!(charge.parse_value::<f64>().unwrap(), 87.0);
+
+        let voltage = Variable::parse(key::INPUT_VOLTAGE, "n/a".into());
+        assert!(matches!(
+            voltage.parse_value::<f64>(),
+            Err(crate::ClientError::Nut(crate::NutError::ParseValue(_)))
+        ));
+    }
+
+    #[test]
+    fn test_variable_raw() {
+        let uptime = Variable::parse(key::DEVICE_UPTIME, "3600".into());
+        assert_eq!(uptime, Variable::DeviceUptime(Duration::from_secs(3600)));
+        assert_eq!(uptime.raw(), "3600");
+
+        let uptime = Variable::parse(key::DEVICE_UPTIME, "0042".into());
+        assert_eq!(
+            uptime,
+            Variable::Other((key::DEVICE_UPTIME.into(), "0042".into()))
+        );
+        assert_eq!(uptime.raw(), "0042");
+
+        let device_type = Variable::parse(key::DEVICE_TYPE, "modem".into());
+        assert_eq!(device_type.raw(), "modem");
+        assert_eq!(device_type.value(), "modem");
     }
 }
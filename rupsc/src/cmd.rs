@@ -4,9 +4,7 @@ use rups::blocking::Connection;
 use rups::Config;
 
 /// Lists each UPS on the upsd server, one per line.
-pub fn list_devices(config: Config, with_description: bool) -> anyhow::Result<()> {
-    let mut conn = connect(config)?;
-
+pub fn list_devices(conn: &mut Connection, with_description: bool) -> anyhow::Result<()> {
     for (name, description) in conn.list_ups()? {
         if with_description {
             println!("{}: {}", name, description);
@@ -15,42 +13,37 @@ pub fn list_devices(config: Config, with_description: bool) -> anyhow::Result<()
         }
     }
 
-    logout(conn)
+    Ok(())
 }
 
-pub fn print_variable(config: Config, ups_name: &str, variable: &str) -> anyhow::Result<()> {
-    let mut conn = connect(config)?;
-
+pub fn print_variable(conn: &mut Connection, ups_name: &str, variable: &str) -> anyhow::Result<()> {
     let variable = conn.get_var(ups_name, variable)?;
     println!("{}", variable.value());
 
-    logout(conn)
+    Ok(())
 }
 
-pub fn list_variables(config: Config, ups_name: &str) -> anyhow::Result<()> {
-    let mut conn = connect(config)?;
-
+pub fn list_variables(conn: &mut Connection, ups_name: &str) -> anyhow::Result<()> {
     for var in conn.list_vars(ups_name)? {
         println!("{}", var);
     }
 
-    logout(conn)
+    Ok(())
 }
 
-pub fn list_clients(config: Config, ups_name: &str) -> anyhow::Result<()> {
-    let mut conn = connect(config)?;
-
+pub fn list_clients(conn: &mut Connection, ups_name: &str) -> anyhow::Result<()> {
     for client_ip in conn.list_clients(ups_name)? {
         println!("{}", client_ip);
     }
 
-    logout(conn)
+    Ok(())
 }
 
-fn connect(config: Config) -> anyhow::Result<Connection> {
-    Connection::new(&config).with_context(|| format!("Failed to connect to upsd: {:?}", &config))
+/// Opens the connection to upsd, which is shared by all operations of the invocation.
+pub fn connect(config: Config) -> anyhow::Result<Connection> {
+    Connection::new(&config).with_context(|| format!("Failed to connect to upsd: {}", &config))
 }
 
-fn logout(conn: Connection) -> anyhow::Result<()> {
+pub fn logout(conn: Connection) -> anyhow::Result<()> {
     conn.close().with_context(|| "Failed to close gracefully")
 }
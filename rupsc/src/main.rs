@@ -89,24 +89,30 @@ fn main() -> anyhow::Result<()> {
         .with_insecure_ssl(insecure_ssl)
         .build();
 
-    if args.is_present("list") {
-        return cmd::list_devices(config, false);
-    }
+    let list = args.is_present("list");
+    let list_full = args.is_present("list-full");
 
-    if args.is_present("list-full") {
-        return cmd::list_devices(config, true);
-    }
+    // Validate arguments before connecting
+    let ups_name = if list || list_full {
+        None
+    } else {
+        Some(get_ups_name(&server)?)
+    };
 
-    if args.is_present("clients") {
-        return cmd::list_clients(config, get_ups_name(&server)?);
-    }
+    let mut conn = cmd::connect(config)?;
 
-    // Fallback: prints one variable (or all of them)
-    if let Some(variable) = args.value_of("variable") {
-        cmd::print_variable(config, get_ups_name(&server)?, variable)
-    } else {
-        cmd::list_variables(config, get_ups_name(&server)?)
+    match ups_name {
+        // `--list` takes precedence over `--list-full`
+        None => cmd::list_devices(&mut conn, list_full && !list)?,
+        Some(ups_name) if args.is_present("clients") => cmd::list_clients(&mut conn, ups_name)?,
+        // Fallback: prints one variable (or all of them)
+        Some(ups_name) => match args.value_of("variable") {
+            Some(variable) => cmd::print_variable(&mut conn, ups_name, variable)?,
+            None => cmd::list_variables(&mut conn, ups_name)?,
+        },
     }
+
+    cmd::logout(conn)
 }
 
 fn get_ups_name<'a>(server: &'a UpsdName) -> anyhow::Result<&'a str> {